@@ -7,6 +7,7 @@ fn make_app() -> clap::App<'static> {
         .author(constants::AUTHOR)
         .about(constants::ABOUT)
         .arg("-c, --command=[CMD] 'Runs a command'")
+        .arg("-s, --strict 'Stops at the first error and exits with status 1'")
         .arg(
             Arg::new("INPUT")
                 .about("Sets the input file to use")
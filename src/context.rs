@@ -1,48 +1,82 @@
-use crate::result::Result;
+use crate::result::{ErrorKind, Result};
 use owning_ref::MutexGuardRefMut;
 use std::clone::Clone;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::env;
-use std::sync::{Arc, Mutex};
+use std::fmt;
+use std::sync::{Arc, Mutex, Weak};
 
-#[derive(Debug, Clone)]
-pub struct Context<'a> {
-    pub parent: Arc<Option<&'a Context<'a>>>,
+/// Contexts own their parents, so a context can be moved to another thread
+#[derive(Clone)]
+pub struct Context {
+    pub parent: Option<Arc<Context>>,
     pub vars: Arc<Mutex<HashMap<String, Result>>>,
-    pub node: Option<&'a Result>,
+    /// Names of the own vars which were declared with const
+    pub consts: Arc<Mutex<HashSet<String>>>,
+    pub node: Option<Arc<Result>>,
+    /// Blocks stop at the first statement evaluating to an error, set inside of try blocks
+    pub abort_on_error: bool,
 }
 
+/// Context a function was defined in. A function stored in a variable of that context only holds it weakly,
+/// otherwise the context and the function would keep each other alive
+#[derive(Clone, Debug)]
+pub enum Env {
+    Strong(Arc<Context>),
+    Weak(Weak<Context>),
+}
+
+impl Env {
+    pub fn upgrade(&self) -> Option<Arc<Context>> {
+        match self {
+            Env::Strong(ctx) => {
+                return Some(ctx.clone());
+            }
+            Env::Weak(ctx) => {
+                return ctx.upgrade();
+            }
+        }
+    }
+}
 
-impl<'a> Context<'a> {
-    pub fn new() -> Context<'a> {
+// Closures reference the context they were defined in, printing the values could recurse forever
+impl fmt::Debug for Context {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let vars = self.vars.lock().unwrap();
+        return f.debug_struct("Context").field("vars", &vars.keys()).finish();
+    }
+}
+
+
+impl Context {
+    pub fn new() -> Context {
         return Context {
-            parent: Arc::new(Option::None),
+            parent: Option::None,
             vars: Arc::new(Mutex::new(HashMap::new())),
+            consts: Arc::new(Mutex::new(HashSet::new())),
             node: None,
+            abort_on_error: false,
         };
     }
 
-    pub fn from_parent(parent: &'a Context, node: Option<&'a Result>) -> Context<'a> {
+    pub fn from_parent(parent: Arc<Context>, node: Option<Arc<Result>>) -> Context {
+        let abort_on_error = parent.abort_on_error;
         return Context {
-            parent: Arc::new(Option::Some(parent)),
+            parent: Option::Some(parent),
             vars: Arc::new(Mutex::new(HashMap::new())),
+            consts: Arc::new(Mutex::new(HashSet::new())),
             node: node,
+            abort_on_error: abort_on_error,
         };
     }
 
-    pub fn from_vars(
-        parent: &'a Context,
-        vars: HashMap<String, Result>,
-        node: Option<&'a Result>,
-    ) -> Context<'a> {
-        return Context {
-            parent: Arc::new(Option::Some(parent)),
-            vars: Arc::new(Mutex::new(vars)),
-            node: node,
-        };
+    /// Context of a function call, the variables are looked up in the environment the function was defined in
+    pub fn from_closure(env: Arc<Context>, caller: &Context, node: Option<Arc<Result>>) -> Context {
+        let mut ctx = Context::from_parent(env, node);
+        ctx.abort_on_error = caller.abort_on_error;
+        return ctx;
     }
 
-    //Cloning the data is slow, a borrow_var variable would be great, especially for assigning values to dicts or arrays
     pub fn var(&self, name: &str) -> Result {
         if name.starts_with("$") {
             match env::var(name[1..].to_owned()) {
@@ -50,7 +84,7 @@ impl<'a> Context<'a> {
                     return Result::String(val);
                 }
                 Err(_) => {
-                    return Result::Error("Variable ".to_string() + name + " not found");
+                    return Result::error(ErrorKind::NameError, "Variable ".to_string() + name + " not found");
                 }
             }
         } else {
@@ -58,7 +92,7 @@ impl<'a> Context<'a> {
                 return self.var_from_dict(name);
             } else {
                 if let Some(value) = self.var_recursively(name) {
-                    return value.clone();
+                    return Context::load(value.clone());
                 } else {
                     return Result::None;
                 }
@@ -69,22 +103,23 @@ impl<'a> Context<'a> {
     fn var_from_dict(&self, name: &str) -> Result {
         let mut parts = name.split("::");
         let dictname = parts.next().unwrap();
-        if let Some(res) = self.var_recursively(dictname) {
-            let mut res = &*res;
+        let root = self.var_recursively(dictname).map(|res| res.clone());
+        if let Some(mut res) = root {
             for part in parts {
-                if let Result::Dict(dict) = res {
-                    if dict.contains_key(part) {
-                        res = dict.get(part).unwrap();
-                    } else {
-                        return Result::Error("Dict does not contain key ".to_owned() + part);
-                    }
+                let next = if let Result::Dict(dict) = &res {
+                    dict.lock().unwrap().get(part).cloned()
                 } else {
-                    return Result::Error(":: can only be used with dicts".to_string());
+                    return Result::error(ErrorKind::TypeError, ":: can only be used with dicts".to_string());
+                };
+                if let Some(next) = next {
+                    res = next;
+                } else {
+                    return Result::error(ErrorKind::KeyError, "Dict does not contain key ".to_owned() + part);
                 }
             }
-            return res.clone();
+            return res;
         } else {
-            return Result::Error("Dict ".to_string() + dictname + " does not exists");
+            return Result::error(ErrorKind::NameError, "Dict ".to_string() + dictname + " does not exists");
         }
     }
 
@@ -93,10 +128,10 @@ impl<'a> Context<'a> {
         name: &str,
     ) -> Option<MutexGuardRefMut<HashMap<String, Result>, Result>> {
         if !self.vars.lock().unwrap().contains_key(name) {
-            if let Option::None = *self.parent {
-                return None;
+            if let Some(parent) = &self.parent {
+                return parent.var_recursively(name);
             } else {
-                return self.parent.unwrap().var_recursively(name);
+                return None;
             }
         } else {
             let mgrm = MutexGuardRefMut::new(self.vars.lock().unwrap());
@@ -104,53 +139,117 @@ impl<'a> Context<'a> {
         }
     }
 
-    pub fn set_var(&self, name: &str, value: Result) {
+    /// Assigns the variable in the context it was declared in, or declares it in this context
+    pub fn set_var(&self, name: &str, value: Result) -> Result {
         if name.starts_with("$") {
             env::set_var(name[1..].to_owned(), value.to_string());
         } else {
             if name.contains("::") {
                 self.set_var_in_dict(name, value);
+            } else if self.is_const(name) {
+                return Result::error(ErrorKind::TypeError, "Constant ".to_string() + name + " can't be reassigned");
             } else if let Option::Some(notfoundvalue) = self.set_var_recursively(name, value) {
+                let notfoundvalue = self.store(notfoundvalue);
                 self.vars
                     .lock()
                     .unwrap()
                     .insert(name.to_string(), notfoundvalue);
             }
         }
+        return Result::None;
+    }
+
+    /// Declares a variable with let or const in this context, it shadows variables of the parents
+    pub fn declare_var(&self, name: &str, value: Result, constant: bool) -> Result {
+        let mut consts = self.consts.lock().unwrap();
+        if consts.contains(name) {
+            return Result::error(ErrorKind::TypeError, "Constant ".to_string() + name + " can't be redeclared");
+        }
+        if constant {
+            consts.insert(name.to_string());
+        }
+        let value = self.store(value);
+        self.vars.lock().unwrap().insert(name.to_string(), value);
+        return Result::None;
+    }
+
+    /// Whether the variable was declared with const in the context it is found in
+    pub fn is_const(&self, name: &str) -> bool {
+        if self.vars.lock().unwrap().contains_key(name) {
+            return self.consts.lock().unwrap().contains(name);
+        } else if let Some(parent) = &self.parent {
+            return parent.is_const(name);
+        }
+        return false;
     }
 
     fn set_var_in_dict(&self, name: &str, value: Result) {
         let mut parts = name.split("::").collect::<Vec<&str>>();
         let last = parts.remove(parts.len() - 1);
-        if let Some(mut mref) = self.var_recursively(&parts.remove(0)) {
-            let mut res = &mut *mref;
+        let root = self.var_recursively(&parts.remove(0)).map(|res| res.clone());
+        if let Some(mut res) = root {
             for part in parts.iter() {
-                if let Result::Dict(dict) = res {
-                    if dict.contains_key(part.to_owned()) {
-                        res = dict.get_mut(part.to_owned()).unwrap();
-                    } else {
-                        return;
-                    }
+                let next = if let Result::Dict(dict) = &res {
+                    dict.lock().unwrap().get(part.to_owned()).cloned()
+                } else {
+                    return;
+                };
+                if let Some(next) = next {
+                    res = next;
+                } else {
+                    return;
                 }
             }
             if let Result::Dict(dict) = res {
-                dict.insert(last.to_owned(), value);
+                dict.lock().unwrap().insert(last.to_owned(), value);
             }
         }
     }
 
     pub fn set_own_var(&self, name: &str, value: Result) {
+        let value = self.store(value);
         self.vars.lock().unwrap().insert(name.to_string(), value);
     }
 
+    /// Functions defined in this context only hold it weakly while they are stored in it.
+    /// Functions inside of arrays and dicts stay strong, the containers are shared and may be returned from the context,
+    /// so a dict of methods defined in a function call, like a class, keeps that call's context alive as long as the dict lives
+    fn store(&self, value: Result) -> Result {
+        if let Result::Function { block, parameters, env: Env::Strong(env) } = &value {
+            if std::ptr::eq(Arc::as_ptr(env), self) {
+                return Result::Function {
+                    block: block.clone(),
+                    parameters: parameters.clone(),
+                    env: Env::Weak(Arc::downgrade(env)),
+                };
+            }
+        }
+        return value;
+    }
+
+    /// Functions read from a variable hold their context again, so they keep working after the context went out of scope
+    fn load(value: Result) -> Result {
+        if let Result::Function { block, parameters, env: Env::Weak(env) } = &value {
+            if let Some(env) = env.upgrade() {
+                return Result::Function {
+                    block: block.clone(),
+                    parameters: parameters.clone(),
+                    env: Env::Strong(env),
+                };
+            }
+        }
+        return value;
+    }
+
     pub fn set_var_recursively(&self, name: &str, value: Result) -> Option<Result> {
         if !self.vars.lock().unwrap().contains_key(name) {
-            if let Option::None = *self.parent {
-                return Option::Some(value);
+            if let Some(parent) = &self.parent {
+                return parent.set_var_recursively(name, value);
             } else {
-                return self.parent.unwrap().set_var_recursively(name, value);
+                return Option::Some(value);
             }
         } else {
+            let value = self.store(value);
             self.vars.lock().unwrap().insert(name.to_string(), value);
             return Option::None;
         }
@@ -163,13 +262,90 @@ impl<'a> Context<'a> {
         for key in keys {
             names.push(key.clone());
         }
-        if let Some(parent) = *self.parent {
+        if let Some(parent) = &self.parent {
             names.append(&mut parent.get_all_var_names());
         }
         return names;
     }
 
-    pub fn me(&self) -> Option<&Result> {
-        return self.node;
+    /// Inside of try blocks or in strict mode blocks stop at the first error
+    pub fn aborts_on_error(&self) -> bool {
+        if self.abort_on_error {
+            return true;
+        }
+        if let Result::Bool(true) = self.var("STRICT") {
+            return true;
+        }
+        return false;
+    }
+
+    pub fn me(&self) -> Option<Arc<Result>> {
+        return self.node.clone();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::Node;
+    use crate::interpreter::Rule;
+    use crate::runtime::test::*;
+
+    fn function(env: Env) -> Result {
+        let block = Node::new(Rule::Block, "".to_string(), Vec::new(), Arc::new("f".to_string()), 1, 1);
+        return Result::Function {
+            block: Arc::new(block),
+            parameters: Arc::new(Vec::new()),
+            env: env,
+        };
+    }
+
+    #[test]
+    fn functions_hold_their_own_context_weakly() {
+        let ctx = Arc::new(Context::new());
+        ctx.set_own_var("f", function(Env::Strong(ctx.clone())));
+        assert_eq!(Arc::strong_count(&ctx), 1);
+        assert!(matches!(ctx.var("f"), Result::Function { env: Env::Strong(_), .. }));
+        let weak = Arc::downgrade(&ctx);
+        drop(ctx);
+        assert!(weak.upgrade().is_none());
+    }
+
+    #[test]
+    fn functions_hold_other_contexts_strongly() {
+        let env = Arc::new(Context::new());
+        let ctx = Context::new();
+        ctx.set_own_var("f", function(Env::Strong(env.clone())));
+        assert_eq!(Arc::strong_count(&env), 2);
+    }
+
+    #[test]
+    fn let_is_local_to_the_block() {
+        assert_eq!(text("i = 1; f = () -> { let i = 5; i }; f(); i"), "1");
+        assert_eq!(text("i = 1; { let i = 2 }; i"), "1");
+        assert_eq!(text("let x = 1; let x = 2; x"), "2");
+    }
+
+    #[test]
+    fn constants_may_not_be_reassigned() {
+        assert_eq!(text("const c = 1; c"), "1");
+        assert_eq!(error_kind("const c = 1; c = 2"), Some(ErrorKind::TypeError));
+        assert_eq!(error_kind("const c = 1; let c = 2"), Some(ErrorKind::TypeError));
+    }
+
+    #[test]
+    fn constant_containers_may_be_changed_in_place() {
+        assert_eq!(text("const d = {}; d[\"a\"] = 2; d::b = 3; d == {\"a\": 2, \"b\": 3}"), "true");
+        assert_eq!(text("const a = [1]; a[0] = 2; push(a, 3); a.push(4); a"), "[ 2, 3, 4 ]");
+        assert_eq!(error_kind("const a = [1]; a = [2]"), Some(ErrorKind::TypeError));
+    }
+
+    #[test]
+    fn closures_capture_by_reference() {
+        assert_eq!(text("make = () -> { n = 0; () -> { n = n + 1; n } }; c = make(); c(); c(); c()"), "3");
+        assert_eq!(text("x = 1; f = () -> { x }; x = 2; f()"), "2");
+        assert_eq!(text("f = () -> { y = 3; () -> { y } }; g = f(); g()"), "3");
+        assert_eq!(text("make = () -> { n = 5; m = {\"get\": () -> { n }}; m }; o = make(); o.get()"), "5");
+        assert_eq!(text("f = () -> { fib = (n) -> { if n < 2 { n } else { fib(n - 1) + fib(n - 2) } }; fib(10) }; f()"), "55");
     }
 }
@@ -1,22 +1,45 @@
 use crate::interpreter::Rule;
+use crate::result::Location;
 use pest::iterators::{Pair};
+use std::sync::Arc;
 
 #[derive(Debug, Clone)]
 pub struct Node{
     pub rule: Rule,
     pub content: String,
-    pub inner: Vec<Node>
+    pub inner: Vec<Node>,
+    pub file: Arc<String>,
+    pub line: usize,
+    pub column: usize
+}
+
+/// Text of a parsed file, maps byte offsets to lines and columns
+pub struct Source<'a>{
+    pub file: Arc<String>,
+    text: &'a str,
+    linestarts: Vec<usize>
 }
 
 impl Node{
-    pub fn new(rule: Rule, content: String, inner: Vec<Node>) -> Node{
+    pub fn new(rule: Rule, content: String, inner: Vec<Node>, file: Arc<String>, line: usize, column: usize) -> Node{
         return Node{
             rule: rule,
             content: content,
-            inner: inner
+            inner: inner,
+            file: file,
+            line: line,
+            column: column
         }
     }
 
+    pub fn location(&self) -> Location{
+        return Location{
+            file: self.file.to_string(),
+            line: self.line,
+            column: self.column
+        };
+    }
+
     pub fn content(&self) -> &String{
         return &self.content;
     }
@@ -30,12 +53,65 @@ impl Node{
     }
 }
 
-pub fn build_ast(pair: Pair<Rule>) -> Node{
+impl<'a> Source<'a>{
+    pub fn new(file: String, text: &'a str) -> Source<'a>{
+        let mut linestarts = vec![0];
+        for (i, c) in text.char_indices(){
+            if c == '\n'{
+                linestarts.push(i + 1);
+            }
+        }
+        return Source{
+            file: Arc::new(file),
+            text: text,
+            linestarts: linestarts
+        };
+    }
+
+    /// Line and column of a byte offset, both start at 1
+    pub fn position(&self, offset: usize) -> (usize, usize){
+        let line = match self.linestarts.binary_search(&offset){
+            Ok(i) => i + 1,
+            Err(i) => i
+        };
+        let column = self.text[self.linestarts[line - 1]..offset].chars().count() + 1;
+        return (line, column);
+    }
+}
+
+pub fn build_ast(pair: Pair<Rule>, source: &Source) -> Node{
     let rule = pair.as_rule();
     let mut text = pair.as_span().as_str().to_owned();
-    let children = pair.into_inner().map(|pair| build_ast(pair)).collect::<Vec<Node>>();
+    let (line, column) = source.position(pair.as_span().start());
+    let children = pair
+        .into_inner()
+        .filter(|pair| !is_closing_token(pair.as_rule()))
+        .map(|pair| build_ast(pair, source))
+        .collect::<Vec<Node>>();
     if children.len() > 0{
         text = "".to_owned();
     }
-    return Node::new(rule, text, children);
-}
\ No newline at end of file
+    return Node::new(rule, text, children, source.file.clone(), line, column);
+}
+
+fn is_closing_token(rule: Rule) -> bool{
+    match rule{
+        Rule::CloseBrace | Rule::CloseBracket | Rule::CloseParen | Rule::Quote => true,
+        _ => false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn positions_start_at_one() {
+        let source = Source::new("f".to_string(), "ab\nc\u{e4}d\n");
+        assert_eq!(source.position(0), (1, 1));
+        assert_eq!(source.position(1), (1, 2));
+        assert_eq!(source.position(3), (2, 1));
+        assert_eq!(source.position(6), (2, 3));
+        assert_eq!(source.position(8), (3, 1));
+    }
+}
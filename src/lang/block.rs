@@ -1,116 +1,342 @@
 use super::*;
 use crate::ast::Node;
-use crate::context::Context;
+use crate::context::{Context, Env};
 use crate::interpreter::Rule;
-use crate::result::{Parameter, Result};
+use crate::result::{Channel, ErrorKind, Frame, Parameter, Result};
 use crate::runtime::Runtime;
-use std::collections::HashMap;
 
 pub fn eval_block(pairs: &Vec<Node>, runtime: Arc<Runtime>, ctx: Arc<Context>) -> Result {
     let mut lastres = Result::None;
     for pair in pairs {
         let res = eval(pair, runtime.clone(), ctx.clone());
-        if let Result::Return(e) = res {
-            return *e;
-        } else {
-            lastres = res;
+        match res {
+            Result::Return(_) | Result::Break(_) | Result::Continue => {
+                return res;
+            }
+            Result::Error(_) if ctx.aborts_on_error() => {
+                // Only try blocks set abort_on_error, strict mode comes from the STRICT variable
+                if !ctx.abort_on_error {
+                    runtime.set_aborted();
+                }
+                return res;
+            }
+            _ => {
+                lastres = res;
+            }
         }
     }
     return lastres;
 }
 
-pub fn eval_function(pairs: &Vec<Node>, runtime: Arc<Runtime>, ctx: Arc<Context>) -> Result {
+/// Unwraps the value of a return at a function boundary, break and continue may not leave a function
+pub fn function_result(res: Result) -> Result {
+    match res {
+        Result::Return(e) => {
+            return *e;
+        }
+        Result::Break(_) | Result::Continue => {
+            return Result::error(ErrorKind::RuntimeError, "break and continue may only be used inside of loops".to_string());
+        }
+        _ => {
+            return res;
+        }
+    }
+}
+
+/// Records the call site when an error escapes from a user function
+pub fn trace_call(mut res: Result, name: &str, callsite: Option<&Node>) -> Result {
+    if let Result::Error(e) = &mut res {
+        e.trace.push(Frame {
+            function: name.to_string(),
+            location: callsite.map(|node| node.location()),
+        });
+    }
+    return res;
+}
+
+/// Handles the result of one loop iteration, returns Some if the loop has to be left with the given result
+fn loop_control(res: Result, ctx: &Context) -> Option<Result> {
+    match res {
+        Result::Break(e) => {
+            return Some(*e);
+        }
+        Result::Return(_) => {
+            return Some(res);
+        }
+        Result::Error(_) if ctx.aborts_on_error() => {
+            return Some(res);
+        }
+        _ => {
+            return None;
+        }
+    }
+}
+
+pub fn eval_function(pairs: &Vec<Node>, ctx: Arc<Context>) -> Result {
     let mut iter = pairs.iter();
     let first = iter.next().unwrap();
     let block;
     let mut params: Vec<Parameter> = Vec::new();
     if let Rule::FunctionParams = first.rule() {
         for param in first.inner() {
-            let inner = param.inner();
-            let ident = inner.first().unwrap().content();
-            let mut defaultvalue = None;
-            if inner.len() == 2 {
-                defaultvalue = Some(eval(inner.get(1).unwrap(), runtime.clone(), ctx.clone()));
+            let mut inner = param.inner().iter().peekable();
+            let variadic = inner.next_if(|node| node.rule == Rule::Variadic).is_some();
+            let ident = inner.next().unwrap().content();
+            let default = inner.next().cloned();
+            if variadic && (default.is_some() || params.iter().any(|param| param.variadic)) {
+                return Result::error(ErrorKind::ArgumentError, "A function may only have one ...".to_string() + ident + " parameter and it can't have a default value");
             }
             params.push(Parameter {
-                defaultvalue: defaultvalue,
                 name: ident.clone(),
+                default: default,
+                variadic: variadic,
             });
         }
         block = iter.next().unwrap().clone();
     } else {
         block = first.clone();
     }
-    let mut vars = HashMap::new();
-    if let Some(_) = *ctx.parent {
-        vars = ctx.vars.lock().unwrap().clone();
+    return Result::Function {
+        block: Arc::new(block),
+        parameters: Arc::new(params),
+        env: Env::Strong(ctx),
+    };
+}
+
+/// Collects the value of an iteration for loops in expressions, a break without value ends the loop with the collected values
+fn loop_iteration(res: Result, collected: &mut Option<Vec<Result>>, ctx: &Context) -> Option<Result> {
+    if let Some(values) = collected {
+        match &res {
+            Result::Break(value) if matches!(**value, Result::None) => {
+                return Some(Result::array(std::mem::take(values)));
+            }
+            Result::Break(_) | Result::Return(_) | Result::Continue => {}
+            Result::Error(_) if ctx.aborts_on_error() => {}
+            _ => {
+                values.push(res.clone());
+            }
+        }
     }
+    return loop_control(res, ctx);
+}
 
-    return Result::Function {
-        block: block,
-        parameters: params,
-        vars: vars,
+/// Value of a finished loop, none or the collected values
+fn loop_result(collected: Option<Vec<Result>>) -> Result {
+    if let Some(values) = collected {
+        return Result::array(values);
+    }
+    return Result::None;
+}
+
+/// Runs the loop body for one item, `for a, b in x` destructures every item like `[a, b]`
+fn eval_loopbody(
+    targets: &[Node],
+    item: Result,
+    block: &Node,
+    collected: &mut Option<Vec<Result>>,
+    runtime: Arc<Runtime>,
+    ctx: &Arc<Context>,
+) -> Option<Result> {
+    let mut bindings = Vec::new();
+    let bound = if targets.len() == 1 {
+        destructure(&targets[0], item, &mut bindings, runtime.clone(), ctx.clone())
+    } else if let Result::Array(arr) = item {
+        let values = arr.lock().unwrap().clone();
+        destructure_array(targets, values, &mut bindings, runtime.clone(), ctx.clone())
+    } else {
+        Result::error(ErrorKind::TypeError, "For loops with several variables need arrays as items, not ".to_string() + &item.typename())
     };
+    if let Result::Error(e) = bound {
+        return Some(Result::Error(e));
+    }
+    let newctx = Context::from_parent(ctx.clone(), ctx.me());
+    for (name, value) in bindings {
+        newctx.set_own_var(&name, value);
+    }
+    return loop_iteration(eval(block, runtime, Arc::new(newctx)), collected, ctx);
 }
 
-pub fn eval_forloop(inner: &Vec<Node>, runtime: Arc<Runtime>, ctx: Arc<Context>) -> Result {
-    let mut iter = inner.iter();
-    let varname = iter.next().unwrap().content().as_str();
-    let range = eval(iter.next().unwrap(), runtime.clone(), ctx.clone());
+pub fn eval_forloop(inner: &Vec<Node>, collect: bool, runtime: Arc<Runtime>, ctx: Arc<Context>) -> Result {
+    let mut collected = if collect { Some(Vec::new()) } else { None };
+    let targets = &inner[..inner.len() - 2];
+    let block = &inner[inner.len() - 1];
+    let range = eval(&inner[inner.len() - 2], runtime.clone(), ctx.clone());
     if let Result::Error(e) = range {
         return Result::Error(e);
+    } else if range.is_control_flow() {
+        return range;
     } else if let Result::Array(vec) = range {
-        let block = iter.next().unwrap();
-        for i in vec {
-            let newctx = Context::from_parent(&*ctx, ctx.me());
-            newctx.set_own_var(varname, i);
-            eval(block, runtime.clone(), Arc::new(newctx));
-        }
-    } else if let Result::Range{start,end} = range {
-        let block = iter.next().unwrap();
-        for i in start..end {
-            let newctx = Context::from_parent(&*ctx, ctx.me());
-            newctx.set_own_var(varname, Result::Int(i as i64));
-            eval(block, runtime.clone(), Arc::new(newctx));
+        let vec = vec.lock().unwrap().clone();
+        for item in vec {
+            if let Some(res) = eval_loopbody(targets, item, block, &mut collected, runtime.clone(), &ctx) {
+                return res;
+            }
+        }
+    } else if let Result::Dict(map) = range {
+        // A single variable gets the keys, two variables get the keys and the values
+        let map = map.lock().unwrap().clone();
+        for (key, value) in map {
+            let item = if targets.len() == 1 {
+                Result::String(key)
+            } else {
+                Result::array(vec![Result::String(key), value])
+            };
+            if let Some(res) = eval_loopbody(targets, item, block, &mut collected, runtime.clone(), &ctx) {
+                return res;
+            }
+        }
+    } else if let Result::Range(range) = range {
+        for i in range.iter() {
+            if let Some(res) = eval_loopbody(targets, Result::Int(i), block, &mut collected, runtime.clone(), &ctx) {
+                return res;
+            }
+        }
+    } else if let Result::Channel(channel) = range {
+        while let Some(value) = channel.recv() {
+            if let Some(res) = eval_loopbody(targets, value, block, &mut collected, runtime.clone(), &ctx) {
+                return res;
+            }
         }
     }  else {
-        return Result::Error("For loop can only loop over arrays and ranges".to_string());
+        return Result::error(ErrorKind::TypeError, "For loop can only loop over arrays, dicts, ranges and channels".to_string());
     }
-    return Result::None;
+    return loop_result(collected);
 }
 
-pub fn eval_whileloop(inner: &Vec<Node>, runtime: Arc<Runtime>, ctx: Arc<Context>) -> Result {
+pub fn eval_whileloop(inner: &Vec<Node>, collect: bool, runtime: Arc<Runtime>, ctx: Arc<Context>) -> Result {
+    let mut collected = if collect { Some(Vec::new()) } else { None };
     let mut iter = inner.iter();
     let expr = iter.next().unwrap();
     let block = iter.next().unwrap();
-    while let Result::Bool(true) = eval(expr, runtime.clone(), ctx.clone()) {
-        let newctx = Context::from_parent(&*ctx, ctx.me());
-        eval(block, runtime.clone(), Arc::new(newctx));
+    loop {
+        match eval(expr, runtime.clone(), ctx.clone()) {
+            Result::Bool(true) => {}
+            Result::Error(e) => {
+                return Result::Error(e);
+            }
+            res if res.is_control_flow() => {
+                return res;
+            }
+            _ => {
+                break;
+            }
+        }
+        let newctx = Context::from_parent(ctx.clone(), ctx.me());
+        if let Some(res) = loop_iteration(eval(block, runtime.clone(), Arc::new(newctx)), &mut collected, &ctx) {
+            return res;
+        }
     }
-    return Result::None;
+    return loop_result(collected);
 }
 
+/// Evaluates the first arm whose pattern matches the value and whose guard is true
+pub fn eval_match(inner: &Vec<Node>, runtime: Arc<Runtime>, ctx: Arc<Context>) -> Result {
+    let value = eval(&inner[1], runtime.clone(), ctx.clone());
+    if value.is_control_flow() {
+        return value;
+    }
+    for arm in &inner[2..] {
+        let arm = arm.inner();
+        let mut bindings = Vec::new();
+        if let Result::Error(_) = destructure(&arm[0], value.clone(), &mut bindings, runtime.clone(), ctx.clone()) {
+            continue;
+        }
+        let newctx = Arc::new(Context::from_parent(ctx.clone(), ctx.me()));
+        for (name, value) in bindings {
+            newctx.set_own_var(&name, value);
+        }
+        if let Rule::MatchGuard = arm[1].rule() {
+            match eval(&arm[1].inner()[0], runtime.clone(), newctx.clone()) {
+                Result::Bool(true) => {}
+                Result::Error(e) => {
+                    return Result::Error(e);
+                }
+                res if res.is_control_flow() => {
+                    return res;
+                }
+                _ => {
+                    continue;
+                }
+            }
+        }
+        return eval(arm.last().unwrap(), runtime, newctx);
+    }
+    if let Result::Error(e) = value {
+        return Result::Error(e);
+    }
+    return Result::error(ErrorKind::ValueError, "No match arm matches ".to_string() + &value.to_string());
+}
+
+/// An error in a condition is the result of the whole conditional, so try blocks and strict mode see it, return, break and continue leave it
 pub fn eval_conditional(inner: &Vec<Node>, runtime: Arc<Runtime>, ctx: Arc<Context>) -> Result {
     let mut iter = inner.iter();
-    let expr = iter.next().unwrap();
-    if let Result::Bool(true) = eval(expr, runtime.clone(), ctx.clone()) {
-        let newctx = Context::from_parent(&*ctx, ctx.me());
-        return eval(iter.next().unwrap(), runtime, Arc::new(newctx));
-    } else {
-        iter.next().unwrap();
-        while let Some(node) = iter.next() {
-            if let Rule::Expr = node.rule() {
-                if let Result::Bool(true) = eval(node, runtime.clone(), ctx.clone()) {
-                    let newctx = Context::from_parent(&*ctx, ctx.me());
+    while let Some(node) = iter.next() {
+        if let Rule::Expr = node.rule() {
+            match eval(node, runtime.clone(), ctx.clone()) {
+                Result::Bool(true) => {
+                    let newctx = Context::from_parent(ctx.clone(), ctx.me());
                     return eval(iter.next().unwrap(), runtime, Arc::new(newctx));
-                } else {
+                }
+                Result::Error(e) => {
+                    return Result::Error(e);
+                }
+                res if res.is_control_flow() => {
+                    return res;
+                }
+                _ => {
                     iter.next().unwrap();
                 }
-            } else {
-                let newctx = Context::from_parent(&*ctx, ctx.me());
-                return eval(node, runtime, Arc::new(newctx));
             }
+        } else {
+            let newctx = Context::from_parent(ctx.clone(), ctx.me());
+            return eval(node, runtime, Arc::new(newctx));
+        }
+    }
+    return Result::None;
+}
+
+/// Evaluates the try block until a statement evaluates to an error, the error is bound to the optional ident in the catch block
+pub fn eval_trycatch(inner: &Vec<Node>, runtime: Arc<Runtime>, ctx: Arc<Context>) -> Result {
+    let mut iter = inner.iter();
+    let tryblock = iter.next().unwrap();
+    let mut trycontext = Context::from_parent(ctx.clone(), ctx.me());
+    trycontext.abort_on_error = true;
+    let res = eval(tryblock, runtime.clone(), Arc::new(trycontext));
+    if let Result::Error(e) = res {
+        let newctx = Context::from_parent(ctx.clone(), ctx.me());
+        let mut next = iter.next().unwrap();
+        if let Rule::Ident = next.rule() {
+            newctx.set_own_var(next.content(), error_dict(&e));
+            next = iter.next().unwrap();
+        }
+        return eval(next, runtime, Arc::new(newctx));
+    }
+    return res;
+}
+
+/// Evaluates the next argument of a builtin, a missing argument is an error instead of a panic
+fn next_arg(
+    iter: &mut std::slice::Iter<Node>,
+    func: &str,
+    runtime: Arc<Runtime>,
+    ctx: Arc<Context>,
+) -> Result {
+    if let Some(node) = iter.next() {
+        return eval(node, runtime, ctx);
+    }
+    return Result::error(ErrorKind::ArgumentError, "Function ".to_string() + func + " is missing an argument");
+}
+
+/// Evaluates the next argument of a builtin if it was passed, otherwise none, it may be passed by its name
+fn optional_arg(iter: &mut std::slice::Iter<Node>, name: &str, runtime: Arc<Runtime>, ctx: Arc<Context>) -> Result {
+    if let Some(node) = iter.next() {
+        if let Rule::KeywordArgument = node.rule() {
+            if node.inner()[0].content() != name {
+                return Result::error(ErrorKind::ArgumentError, "Unknown argument ".to_string() + node.inner()[0].content() + ", expected " + name);
+            }
+            return eval(&node.inner()[1], runtime, ctx);
         }
+        return eval(node, runtime, ctx);
     }
     return Result::None;
 }
@@ -121,74 +347,81 @@ pub fn eval_call(inner: &Vec<Node>, runtime: Arc<Runtime>, ctx: Arc<Context>) ->
     match func {
         //Math
         "abs" => {
-            return abs(eval(iter.next().unwrap(), runtime, ctx));
+            return abs(next_arg(&mut iter, func, runtime, ctx));
         }
         "ceil" => {
-            return ceil(eval(iter.next().unwrap(), runtime, ctx));
+            return ceil(next_arg(&mut iter, func, runtime, ctx));
         }
         "floor" => {
-            return floor(eval(iter.next().unwrap(), runtime, ctx));
+            return floor(next_arg(&mut iter, func, runtime, ctx));
         }
         "round" => {
-            return round(eval(iter.next().unwrap(), runtime, ctx));
+            return round(next_arg(&mut iter, func, runtime, ctx));
         }
         "signum" => {
-            return signum(eval(iter.next().unwrap(), runtime, ctx));
+            return signum(next_arg(&mut iter, func, runtime, ctx));
         }
         "sin" => {
-            return sin(eval(iter.next().unwrap(), runtime, ctx));
+            return sin(next_arg(&mut iter, func, runtime, ctx));
         }
         "cos" => {
-            return cos(eval(iter.next().unwrap(), runtime, ctx));
+            return cos(next_arg(&mut iter, func, runtime, ctx));
         }
         "tan" => {
-            return tan(eval(iter.next().unwrap(), runtime, ctx));
+            return tan(next_arg(&mut iter, func, runtime, ctx));
         }
         "asin" => {
-            return asin(eval(iter.next().unwrap(), runtime, ctx));
+            return asin(next_arg(&mut iter, func, runtime, ctx));
         }
         "acos" => {
-            return acos(eval(iter.next().unwrap(), runtime, ctx));
+            return acos(next_arg(&mut iter, func, runtime, ctx));
         }
         "atan" => {
-            return atan(eval(iter.next().unwrap(), runtime, ctx));
+            return atan(next_arg(&mut iter, func, runtime, ctx));
         }
         "sinh" => {
-            return sinh(eval(iter.next().unwrap(), runtime, ctx));
+            return sinh(next_arg(&mut iter, func, runtime, ctx));
         }
         "cosh" => {
-            return cosh(eval(iter.next().unwrap(), runtime, ctx));
+            return cosh(next_arg(&mut iter, func, runtime, ctx));
         }
         "tanh" => {
-            return tanh(eval(iter.next().unwrap(), runtime, ctx));
+            return tanh(next_arg(&mut iter, func, runtime, ctx));
         }
         "asinh" => {
-            return asinh(eval(iter.next().unwrap(), runtime, ctx));
+            return asinh(next_arg(&mut iter, func, runtime, ctx));
         }
         "acosh" => {
-            return acosh(eval(iter.next().unwrap(), runtime, ctx));
+            return acosh(next_arg(&mut iter, func, runtime, ctx));
         }
         "atanh" => {
-            return atanh(eval(iter.next().unwrap(), runtime, ctx));
+            return atanh(next_arg(&mut iter, func, runtime, ctx));
         }
         "log" => {
             return log(
-                eval(iter.next().unwrap(), runtime.clone(), ctx.clone()),
-                eval(iter.next().unwrap(), runtime, ctx),
+                next_arg(&mut iter, func, runtime.clone(), ctx.clone()),
+                next_arg(&mut iter, func, runtime, ctx),
+            );
+        }
+        "linspace" => {
+            return linspace(
+                next_arg(&mut iter, func, runtime.clone(), ctx.clone()),
+                next_arg(&mut iter, func, runtime.clone(), ctx.clone()),
+                next_arg(&mut iter, func, runtime, ctx),
             );
         }
         "lg" => {
             return log(
-                eval(iter.next().unwrap(), runtime, ctx),
+                next_arg(&mut iter, func, runtime, ctx),
                 Result::Float(10.0),
             );
         }
         "ld" => {
-            return log(eval(iter.next().unwrap(), runtime, ctx), Result::Float(2.0));
+            return log(next_arg(&mut iter, func, runtime, ctx), Result::Float(2.0));
         }
         "ln" => {
             return log(
-                eval(iter.next().unwrap(), runtime, ctx),
+                next_arg(&mut iter, func, runtime, ctx),
                 Result::Float(std::f64::consts::E),
             );
         }
@@ -198,19 +431,19 @@ pub fn eval_call(inner: &Vec<Node>, runtime: Arc<Runtime>, ctx: Arc<Context>) ->
 
         //Types
         "type" => {
-            return Result::String(eval(iter.next().unwrap(), runtime, ctx).typename());
+            return Result::String(next_arg(&mut iter, func, runtime, ctx).typename());
         }
         "int" => {
-            return cast_int(eval(iter.next().unwrap(), runtime, ctx));
+            return cast_int(next_arg(&mut iter, func, runtime, ctx));
         }
         "float" => {
-            return cast_float(eval(iter.next().unwrap(), runtime, ctx));
+            return cast_float(next_arg(&mut iter, func, runtime, ctx));
         }
         "bool" => {
-            return cast_bool(eval(iter.next().unwrap(), runtime, ctx));
+            return cast_bool(next_arg(&mut iter, func, runtime, ctx));
         }
         "string" => {
-            return cast_string(eval(iter.next().unwrap(), runtime, ctx));
+            return display(next_arg(&mut iter, func, runtime.clone(), ctx.clone()), runtime, ctx);
         }
         "print" => {
             return print(iter, runtime, ctx);
@@ -219,28 +452,74 @@ pub fn eval_call(inner: &Vec<Node>, runtime: Arc<Runtime>, ctx: Arc<Context>) ->
             return println(iter, runtime, ctx);
         }
         "len" => {
-            return len(eval(iter.next().unwrap(), runtime, ctx));
+            return len(next_arg(&mut iter, func, runtime, ctx));
+        }
+        "enumerate" => {
+            return enumerate(next_arg(&mut iter, func, runtime, ctx));
         }
         "each" => {
             return each(
-                eval(iter.next().unwrap(), runtime.clone(), ctx.clone()),
-                eval(iter.next().unwrap(), runtime.clone(), ctx.clone()),
+                next_arg(&mut iter, func, runtime.clone(), ctx.clone()),
+                next_arg(&mut iter, func, runtime.clone(), ctx.clone()),
+                inner.first(),
                 runtime,
                 ctx,
             );
         }
         "map" => {
             return map(
-                eval(iter.next().unwrap(), runtime.clone(), ctx.clone()),
-                eval(iter.next().unwrap(), runtime.clone(), ctx.clone()),
+                next_arg(&mut iter, func, runtime.clone(), ctx.clone()),
+                next_arg(&mut iter, func, runtime.clone(), ctx.clone()),
+                inner.first(),
+                runtime,
+                ctx,
+            );
+        }
+        "push" => {
+            return push(
+                next_arg(&mut iter, func, runtime.clone(), ctx.clone()),
+                next_arg(&mut iter, func, runtime, ctx),
+            );
+        }
+        "pop" => {
+            return pop(next_arg(&mut iter, func, runtime, ctx));
+        }
+        "class" => {
+            return make_class(
+                next_arg(&mut iter, func, runtime.clone(), ctx.clone()),
+                optional_arg(&mut iter, "parent", runtime, ctx),
+            );
+        }
+        "copy" => {
+            return next_arg(&mut iter, func, runtime, ctx).copy();
+        }
+        "deepcopy" => {
+            return next_arg(&mut iter, func, runtime, ctx).deepcopy();
+        }
+        "pmap" => {
+            return pmap(
+                next_arg(&mut iter, func, runtime.clone(), ctx.clone()),
+                next_arg(&mut iter, func, runtime.clone(), ctx.clone()),
+                optional_arg(&mut iter, "workers", runtime.clone(), ctx.clone()),
+                inner.first(),
+                runtime,
+                ctx,
+            );
+        }
+        "peach" => {
+            return peach(
+                next_arg(&mut iter, func, runtime.clone(), ctx.clone()),
+                next_arg(&mut iter, func, runtime.clone(), ctx.clone()),
+                optional_arg(&mut iter, "workers", runtime.clone(), ctx.clone()),
+                inner.first(),
                 runtime,
                 ctx,
             );
         }
         "join" => {
             return join(
-                eval(iter.next().unwrap(), runtime.clone(), ctx.clone()),
-                eval(iter.next().unwrap(), runtime.clone(), ctx.clone()),
+                next_arg(&mut iter, func, runtime.clone(), ctx.clone()),
+                next_arg(&mut iter, func, runtime.clone(), ctx.clone()),
                 runtime,
                 ctx,
             );
@@ -252,11 +531,12 @@ pub fn eval_call(inner: &Vec<Node>, runtime: Arc<Runtime>, ctx: Arc<Context>) ->
             return Result::None;
         }
         "cd" => {
-            change_dir(iter.next(), runtime, ctx);
-            return Result::None;
+            return change_dir(iter.next(), runtime, ctx);
         }
         "include" => {
-            include_file(iter.next(), runtime, ctx);
+            if let Result::Error(e) = include_file(iter.next(), runtime, ctx) {
+                return Result::Error(e);
+            }
             return Result::None;
         }
         "clear" | "cls" => {
@@ -270,6 +550,18 @@ pub fn eval_call(inner: &Vec<Node>, runtime: Arc<Runtime>, ctx: Arc<Context>) ->
         "vars" => {
             return vars(inner, runtime, ctx);
         }
+        "throw" | "error" => {
+            return throw(iter.next(), iter.next(), runtime, ctx);
+        }
+        "error_kind" => {
+            return error_field("kind", iter.next(), runtime, ctx);
+        }
+        "error_message" => {
+            return error_field("message", iter.next(), runtime, ctx);
+        }
+        "error_payload" => {
+            return error_field("payload", iter.next(), runtime, ctx);
+        }
         "return" => {
             if let Option::Some(e) = iter.next() {
                 return Result::Return(Box::new(eval(e, runtime, ctx)));
@@ -279,12 +571,44 @@ pub fn eval_call(inner: &Vec<Node>, runtime: Arc<Runtime>, ctx: Arc<Context>) ->
         }
         "me" => {
             if let Some(func) = ctx.clone().me() {
-                return eval_chainedcall(func, &mut iter, runtime, ctx);
+                return eval_chainedcall(&func, &mut iter, "me", inner.first(), runtime, ctx);
             } else {
-                return Result::Error("Me no t possible".to_string());
+                return Result::error(ErrorKind::RuntimeError, "me may only be used inside of functions".to_string());
             }
         }
 
+        //Threads
+        "await" => {
+            return await_task(iter.next(), runtime, ctx);
+        }
+        "tasks" => {
+            return Result::array(runtime.running_tasks().into_iter().map(Result::Task).collect());
+        }
+        "channel" => {
+            return Result::Channel(Channel::new());
+        }
+        "send" => {
+            return channel_send(
+                next_arg(&mut iter, func, runtime.clone(), ctx.clone()),
+                next_arg(&mut iter, func, runtime, ctx),
+            );
+        }
+        "recv" => {
+            return channel_recv(next_arg(&mut iter, func, runtime, ctx));
+        }
+        "try_recv" => {
+            return channel_try_recv(next_arg(&mut iter, func, runtime, ctx));
+        }
+        "recv_timeout" => {
+            return channel_recv_timeout(
+                next_arg(&mut iter, func, runtime.clone(), ctx.clone()),
+                next_arg(&mut iter, func, runtime, ctx),
+            );
+        }
+        "close" => {
+            return channel_close(next_arg(&mut iter, func, runtime, ctx));
+        }
+
         //Time
         "wait" => {
             if let Option::Some(e) = iter.next() {
@@ -298,7 +622,7 @@ pub fn eval_call(inner: &Vec<Node>, runtime: Arc<Runtime>, ctx: Arc<Context>) ->
             return Result::None;
         }
         _ => {
-            return call_function(func, iter, runtime, ctx);
+            return call_function(func, iter, inner.first(), runtime, ctx);
         }
     }
 }
@@ -306,12 +630,13 @@ pub fn eval_call(inner: &Vec<Node>, runtime: Arc<Runtime>, ctx: Arc<Context>) ->
 pub fn call_function(
     name: &str,
     mut iter: std::slice::Iter<Node>,
+    callsite: Option<&Node>,
     runtime: Arc<Runtime>,
     ctx: Arc<Context>,
 ) -> Result {
-    let res = eval_chainedcall(&ctx.var(name), &mut iter, runtime.clone(), ctx.clone());
+    let res = eval_chainedcall(&ctx.var(name), &mut iter, name, callsite, runtime.clone(), ctx.clone());
     if let Result::Error(e) = res {
-        if e == "Term is not a function".to_string() {
+        if e.message == "Term is not a function" {
             return call_sys_function(name, iter, runtime, ctx);
         }
         return Result::Error(e);
@@ -332,48 +657,363 @@ pub fn call_sys_function(
         }
         return exec(path, args.iter());
     }
-    return Result::Error("Function ".to_string() + name + " was not found");
+    return Result::error(ErrorKind::NameError, "Function ".to_string() + name + " was not found");
 }
 
+/// Calls a user function, name and callsite are only used for the stack trace of escaping errors
 pub fn eval_chainedcall(
     input: &Result,
     iter: &mut std::slice::Iter<Node>,
+    name: &str,
+    callsite: Option<&Node>,
+    runtime: Arc<Runtime>,
+    ctx: Arc<Context>,
+) -> Result {
+    if let Result::Function { .. } = input {
+        let arguments = match eval_arguments(iter, runtime.clone(), ctx.clone()) {
+            Ok(arguments) => arguments,
+            Err(res) => {
+                return res;
+            }
+        };
+        return apply_function(input, arguments, None, name, callsite, runtime, ctx);
+    } else if let Some((Result::Function { .. }, _)) = find_method(input, "__call__") {
+        // Objects with __call__ can be called like functions
+        let arguments = match eval_arguments(iter, runtime.clone(), ctx.clone()) {
+            Ok(arguments) => arguments,
+            Err(res) => {
+                return res;
+            }
+        };
+        // The arguments may have removed __call__ again
+        match call_special(input, "__call__", arguments, callsite, runtime, ctx) {
+            Some(res) => {
+                return res;
+            }
+            None => {
+                return Result::error(ErrorKind::TypeError, "__call__ was removed while evaluating the arguments".to_string());
+            }
+        }
+    } else {
+        return Result::error(ErrorKind::TypeError, "Term is not a function".to_string());
+    }
+}
+
+/// Positional and keyword arguments of a call
+pub type Arguments = (Vec<Result>, Vec<(String, Result)>);
+
+/// Evaluates the arguments of a call, keyword arguments are returned separately.
+/// An argument evaluating to return, break or continue stops the evaluation and is returned as Err, so the call is left with it
+pub fn eval_arguments(
+    iter: &mut std::slice::Iter<Node>,
+    runtime: Arc<Runtime>,
+    ctx: Arc<Context>,
+) -> std::result::Result<Arguments, Result> {
+    let mut args = Vec::new();
+    let mut keywords = Vec::new();
+    for exprnode in iter {
+        if let Rule::KeywordArgument = exprnode.rule() {
+            let value = eval(&exprnode.inner()[1], runtime.clone(), ctx.clone());
+            if value.is_control_flow() {
+                return Err(value);
+            }
+            keywords.push((exprnode.inner()[0].content().to_string(), value));
+        } else {
+            let value = eval(exprnode, runtime.clone(), ctx.clone());
+            if value.is_control_flow() {
+                return Err(value);
+            }
+            args.push(value);
+        }
+    }
+    return Ok((args, keywords));
+}
+
+/// Calls a user function with evaluated arguments, methods get their receiver as self and the prototype above them as super
+pub fn apply_function(
+    input: &Result,
+    (args, keywords): Arguments,
+    receiver: Option<(Result, Result)>,
+    name: &str,
+    callsite: Option<&Node>,
     runtime: Arc<Runtime>,
     ctx: Arc<Context>,
 ) -> Result {
     if let Result::Function {
         block,
         parameters,
-        vars,
-    } = input.clone()
+        env,
+    } = input
     {
-        let newctx = Context::from_vars(&*ctx, vars, Some(&input));
-        for param in parameters {
-            if let Some(exprnode) = iter.next() {
-                let expr = eval(exprnode, runtime.clone(), ctx.clone());
-                newctx.set_own_var(&param.name, expr);
-            } else {
-                if let Some(defaultvalue) = param.defaultvalue {
-                    newctx.set_own_var(&param.name, defaultvalue);
-                } else {
-                    return Result::Error("Function parameter needs to be passed, or default value needs to be deckared".to_string());
-                }
+        let env = match env.upgrade() {
+            Some(env) => env,
+            None => {
+                return trace_call(closed_env(), name, callsite);
             }
+        };
+        let newctx = Arc::new(Context::from_closure(env, &ctx, Some(Arc::new(input.clone()))));
+        if let Some((this, parent)) = receiver {
+            newctx.set_own_var("self", this);
+            newctx.set_own_var("super", parent);
+        }
+        if let Result::Error(e) = bind_parameters(parameters, args, keywords, true, name, runtime.clone(), newctx.clone()) {
+            return trace_call(Result::Error(e), name, callsite);
         }
-        return eval(&block, runtime, Arc::new(newctx));
+        let res = function_result(eval(block, runtime, newctx));
+        return trace_call(res, name, callsite);
     } else {
-        return Result::Error("Term is not a function".to_string());
+        return Result::error(ErrorKind::TypeError, "Term is not a function".to_string());
+    }
+}
+
+/// Error of calling a function whose context was already dropped
+pub fn closed_env() -> Result {
+    return Result::error(ErrorKind::RuntimeError, "The context the function was defined in does not exist anymore".to_string());
+}
+
+/// Binds the arguments of a call to the parameters in the context of the call, missing arguments get their default values.
+/// Callbacks of builtins like map are called with more arguments than they may need, so only strict calls report extra arguments
+pub fn bind_parameters(
+    parameters: &Vec<Parameter>,
+    args: Vec<Result>,
+    mut keywords: Vec<(String, Result)>,
+    strict: bool,
+    name: &str,
+    runtime: Arc<Runtime>,
+    ctx: Arc<Context>,
+) -> Result {
+    for (key, _) in &keywords {
+        if !parameters.iter().any(|param| &param.name == key && !param.variadic) {
+            return Result::error(ErrorKind::ArgumentError, "Function ".to_string() + name + " has no parameter " + key);
+        }
+    }
+    let passed = args.len();
+    let mut args = args.into_iter();
+    for param in parameters {
+        let keyword = keywords.iter().position(|(key, _)| key == &param.name && !param.variadic);
+        if param.variadic {
+            ctx.set_own_var(&param.name, Result::array(args.by_ref().collect()));
+        } else if let Some(value) = args.next() {
+            if keyword.is_some() {
+                return Result::error(ErrorKind::ArgumentError, "Function ".to_string() + name + " got the argument " + &param.name + " twice");
+            }
+            ctx.set_own_var(&param.name, value);
+        } else if let Some(pos) = keyword {
+            ctx.set_own_var(&param.name, keywords.remove(pos).1);
+        } else if let Some(default) = &param.default {
+            // Defaults are evaluated in the function call, so they can use the parameters before them
+            let value = eval(default, runtime.clone(), ctx.clone());
+            ctx.set_own_var(&param.name, value);
+        } else {
+            return Result::error(ErrorKind::ArgumentError, "Function ".to_string() + name + " is missing the argument " + &param.name);
+        }
+    }
+    if let Some((key, _)) = keywords.first() {
+        return Result::error(ErrorKind::ArgumentError, "Function ".to_string() + name + " got the argument " + key + " twice");
+    }
+    if strict && args.next().is_some() {
+        let count = parameters.len();
+        let arguments = if count == 1 { " argument, but " } else { " arguments, but " };
+        return Result::error(ErrorKind::ArgumentError, "Function ".to_string() + name + " takes " + &count.to_string() + arguments + &passed.to_string() + " were passed");
+    }
+    return Result::None;
+}
+
+pub fn eval_break(inner: &Vec<Node>, runtime: Arc<Runtime>, ctx: Arc<Context>) -> Result {
+    if let Some(expr) = inner.first() {
+        return Result::Break(Box::new(eval(expr, runtime, ctx)));
+    } else {
+        return Result::Break(Box::new(Result::None));
     }
 }
 
 pub fn eval_statementitem(pair: &Node, runtime: Arc<Runtime>, ctx: Arc<Context>) -> Result {
     match pair.rule() {
         Rule::Block => {
-            let parent: Context = Context::from_parent(&*ctx, ctx.me());
+            let parent: Context = Context::from_parent(ctx.clone(), ctx.me());
             return eval(pair, runtime, Arc::new(parent));
         }
         _ => {
             return eval(pair, runtime, ctx);
         }
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use crate::result::{ErrorKind, Result};
+    use crate::runtime::test::*;
+    use crate::runtime::Runtime;
+
+    #[test]
+    fn break_and_continue_in_for_loops() {
+        assert_eq!(text("r = []; for i in 0..10 { if i == 3 { continue }; if i == 6 { break }; push(r, i) }; r"), "[ 0, 1, 2, 4, 5 ]");
+    }
+
+    #[test]
+    fn control_flow_leaves_expressions() {
+        assert_eq!(text("f = () -> { x = if true { return(5) } else { 2 }; 7 }; f()"), "5");
+        assert_eq!(text("r = []; for i in 0..4 { x = match i { 1 => { continue }, _ => i * 10 }; push(r, x) }; r"), "[ 0, 20, 30 ]");
+        assert_eq!(text("r = []; for i in 0..4 { let x = if i == 2 { break } else { i }; push(r, x) }; r"), "[ 0, 1 ]");
+        assert_eq!(text("f = () -> { [1, return(2)]; 3 }; f()"), "2");
+        assert_eq!(text("f = () -> { {\"a\": return(2)}; 3 }; f()"), "2");
+        assert_eq!(text("g = (x) -> { x }; f = () -> { g(return(2)); 3 }; f()"), "2");
+        assert_eq!(text("g = (x=1) -> { x }; f = () -> { g(x=return(2)); 3 }; f()"), "2");
+        assert_eq!(text("f = () -> { 1 + return(2); 3 }; f()"), "2");
+        assert_eq!(text("f = () -> { d = [0]; d[0] = -return(2); 3 }; f()"), "2");
+        assert_eq!(text("f = () -> { a = [1]; a[return(2)]; 3 }; f()"), "2");
+        assert_eq!(text("r = []; for i in 0..3 { x = 1 < (if i == 1 { continue } else { 2 }); push(r, i) }; r"), "[ 0, 2 ]");
+        assert_eq!(text("f = () -> { (if true { return(2) } else { false }) ? 1 : 0; 3 }; f()"), "2");
+    }
+
+    #[test]
+    fn break_and_continue_in_while_loops() {
+        assert_eq!(text("i = 0; r = []; while i < 10 { i = i + 1; if i % 2 == 0 { continue }; if i > 7 { break }; push(r, i) }; r"), "[ 1, 3, 5, 7 ]");
+    }
+
+    #[test]
+    fn break_only_leaves_the_inner_loop() {
+        assert_eq!(text("r = []; for i in [[1, 2], [3]] { for j in i { if j == 2 { break }; push(r, j) } }; r"), "[ 1, 3 ]");
+    }
+
+    #[test]
+    fn break_may_not_leave_a_function() {
+        assert_eq!(error_kind("f = () -> { break }; f()"), Some(ErrorKind::RuntimeError));
+    }
+
+    #[test]
+    fn try_catches_the_first_error() {
+        assert_eq!(text("c = 0; try { c = 1; throw(\"x\"); c = 2 } catch e { 0 }; c"), "1");
+        assert_eq!(text("try { 1 / 0; 5 } catch e { error_kind(e) }"), "ArithmeticError");
+        assert_eq!(text("try { error(\"bad\") } catch e { [error_kind(e), error_message(e)] }"), "[ UserError, bad ]");
+        assert_eq!(text("try { throw(\"x\") } catch { 1 }"), "1");
+        assert_eq!(text("try { 3 } catch e { 4 }"), "3");
+    }
+
+    #[test]
+    fn try_catches_errors_in_comparisons() {
+        assert_eq!(text("try { [1][5] == 3 } catch e { error_kind(e) }"), "IndexError");
+        assert_eq!(text("try { if [1][5] > 0 { 1 } } catch e { error_kind(e) }"), "IndexError");
+        assert_eq!(text("try { if false { 1 } elif [1][5] { 2 } } catch e { error_kind(e) }"), "IndexError");
+        assert_eq!(text("try { while [1][5] < 3 { 1 } } catch e { error_kind(e) }"), "IndexError");
+    }
+
+    #[test]
+    fn try_catches_errors_of_called_functions() {
+        assert_eq!(text("f = () -> { throw(\"in f\"); 2 }; try { f() } catch e { error_message(e) }"), "in f");
+        assert_eq!(text("try { try { throw(\"a\") } catch e { throw(\"b\") } } catch e { error_message(e) }"), "b");
+    }
+
+    #[test]
+    fn uncaught_throw() {
+        assert_eq!(error_kind("throw(\"x\")"), Some(ErrorKind::UserError));
+    }
+
+    #[test]
+    fn strict_mode_stops_at_the_first_error() {
+        let runtime = Runtime::new_bare();
+        let res = runtime.clone().run("STRICT = true; c = 1; 5 % 0; c = 2".to_string()).unwrap();
+        assert!(matches!(res, Result::Error(_)));
+        assert_eq!(runtime.basectx.var("c").to_string(), "1");
+        assert!(runtime.aborted());
+    }
+
+    #[test]
+    fn strict_mode_stops_at_errors_in_comparisons() {
+        let runtime = Runtime::new_bare();
+        let res = runtime.clone().run("STRICT = true; c = 1; ok = [1][5] == 3; c = 2".to_string()).unwrap();
+        assert!(matches!(res, Result::Error(_)));
+        assert_eq!(runtime.basectx.var("c").to_string(), "1");
+        assert!(runtime.aborted());
+    }
+
+    #[test]
+    fn strict_mode_ignores_caught_errors() {
+        let runtime = Runtime::new_bare();
+        let res = runtime.clone().run("STRICT = true; try { 5 % 0 } catch { 1 }".to_string()).unwrap();
+        assert_eq!(res.to_string(), "1");
+        assert!(!runtime.aborted());
+    }
+
+    #[test]
+    fn errors_are_values_without_strict_mode() {
+        let runtime = Runtime::new_bare();
+        assert_eq!(runtime.clone().run("c = 1; 5 % 0; c = 2; c".to_string()).unwrap().to_string(), "2");
+        assert!(!runtime.aborted());
+    }
+
+    #[test]
+    fn conditionals_and_blocks_as_expressions() {
+        assert_eq!(text("a = 3; b = 5; x = if a > b { a } else { b }; x"), "5");
+        assert_eq!(text("x = if false { 1 } elif true { 7 } else { 2 }; x"), "7");
+        assert_eq!(text("x = if false { 1 }; type(x)"), "none");
+        assert_eq!(text("x = { 1; 2 }; x"), "2");
+    }
+
+    #[test]
+    fn loops_as_expressions_collect_values() {
+        assert_eq!(text("x = for i in 0..3 { i * 2 }; x"), "[ 0, 2, 4 ]");
+        assert_eq!(text("i = 0; x = while i < 3 { i = i + 1; i }; x"), "[ 1, 2, 3 ]");
+        assert_eq!(text("x = for i in 0..5 { if i == 3 { break }; i }; x"), "[ 0, 1, 2 ]");
+    }
+
+    #[test]
+    fn variadic_and_keyword_parameters() {
+        assert_eq!(text("f = (first, ...rest) -> { [first, rest] }; f(1, 2, 3)"), "[ 1, [ 2, 3 ] ]");
+        assert_eq!(text("f = (...r) -> { r }; f()"), "[]");
+        assert_eq!(text("f = (x, verbose=false) -> { [x, verbose] }; f(x=1, verbose=true)"), "[ 1, true ]");
+    }
+
+    #[test]
+    fn defaults_are_evaluated_at_call_time() {
+        assert_eq!(text("f = (a, b=a * 2) -> { b }; f(3)"), "6");
+        assert_eq!(text("c = 0; f = (x=c + 1) -> { x }; c = 5; f()"), "6");
+    }
+
+    #[test]
+    fn invalid_arguments() {
+        assert_eq!(error_kind("f = (a) -> { a }; f(1, 2)"), Some(ErrorKind::ArgumentError));
+        assert_eq!(error_kind("f = (a) -> { a }; f(b=1)"), Some(ErrorKind::ArgumentError));
+        assert_eq!(error_kind("f = (a) -> { a }; f()"), Some(ErrorKind::ArgumentError));
+        assert_eq!(error_kind("f = (a) -> { a }; f(1, a=2)"), Some(ErrorKind::ArgumentError));
+        assert_eq!(error_kind("f = (a, ...r) -> { r }; f(1, r=2)"), Some(ErrorKind::ArgumentError));
+    }
+
+    #[test]
+    fn argument_errors_name_callbacks() {
+        if let Result::Error(e) = run("map([1], (z, y, w) -> { z })[0]") {
+            assert_eq!(e.kind, ErrorKind::ArgumentError);
+            assert_eq!(e.message, "Function <callback of map> is missing the argument w");
+        } else {
+            panic!("the callback should fail");
+        }
+    }
+
+    #[test]
+    fn missing_arguments_of_builtins() {
+        assert_eq!(error_kind("sin()"), Some(ErrorKind::ArgumentError));
+    }
+
+    #[test]
+    fn errors_have_locations() {
+        assert_eq!(text("x = 1\n[1][5]"), "<input>:2:1: IndexError: Index 5 is out of bounds for length 1");
+    }
+
+    #[test]
+    fn errors_have_a_trace_of_calls() {
+        assert_eq!(
+            text("f = () -> { [1][5] }\ng = () -> { f() }\ng()"),
+            "<input>:1:13: IndexError: Index 5 is out of bounds for length 1\n    in f called at <input>:2:13\n    in g called at <input>:3:1"
+        );
+    }
+
+    #[test]
+    fn callbacks_have_a_trace_frame() {
+        if let Result::Error(e) = run("map([1], (z) -> { [1][z] })[0]") {
+            assert_eq!(e.trace.len(), 1);
+            assert_eq!(e.trace[0].function, "<callback of map>");
+            assert_eq!(e.trace[0].location.as_ref().unwrap().to_string(), "<input>:1:1");
+        } else {
+            panic!("the callback should fail");
+        }
+    }
+}
@@ -26,6 +26,7 @@ lazy_static! {
         m.insert("lg", "Calculates the logarithm to base 10");
         m.insert("ld", "Calculates the logarithm to base 2");
         m.insert("ln", "Calculates the logarithm to base e");
+        m.insert("linspace", "linspace(a, b, n) returns n evenly spaced floats from a to b");
         m.insert("rand", "Returns a random value between 0 and 1");
 
         //Type
@@ -40,8 +41,16 @@ lazy_static! {
         m.insert("println", "Prints all args after each other and inserts newlines");
         m.insert("vars", "Print all vars");
         m.insert("len", "Length of an array / string");
+        m.insert("enumerate", "Returns [index, value] pairs of an array, string or range, for i, x in enumerate(arr) { } loops over them");
         m.insert("each", "Executes a function for each value of an array or dict");
         m.insert("map", "Maps a value of an array or dict via a function");
+        m.insert("push", "Appends a value to an array, the array is changed for every variable it is assigned to");
+        m.insert("pop", "Removes the last value of an array and returns it");
+        m.insert("class", "class(methods, parent) creates a class from a dict of methods which inherits the methods of the parent class, Class.new(args) creates an object and calls its init method");
+        m.insert("copy", "Copies an array or dict, the values inside are still shared");
+        m.insert("deepcopy", "Copies an array or dict and every array or dict inside of it");
+        m.insert("pmap", "Maps the values of an array or dict via a function on a pool of threads, pmap(array, fn, workers = n) limits the number of threads");
+        m.insert("peach", "Executes a function for each value of an array or dict on a pool of threads and returns [index, error] pairs of the failed calls, [key, error] for dicts");
         m.insert("join", "Joins an array into a string, using a given delimeter");
 
         //Control
@@ -52,7 +61,33 @@ lazy_static! {
         m.insert("clear", "Clears the screen");
         m.insert("cls", "Clears the screen");
         m.insert("return", "Returns from the current block and returns up to 1 result");
+        m.insert("let", "let x = value declares a variable in the current block, it hides variables with the same name outside of the block");
+        m.insert("const", "const x = value declares a variable in the current block which can't be reassigned, an array or dict it holds can still be changed");
+        m.insert("->", "(a, b = a * 2, ...rest) -> { } defines a function, defaults are evaluated at each call, ...rest collects the remaining arguments, f(a = 1) passes arguments by name");
+        m.insert("if", "if cond { } elif cond { } else { } evaluates to the value of the chosen block, so x = if a > b { a } else { b } works, cond ? a : b is the short form");
+        m.insert("for", "for x in collection { } loops over arrays, dicts, ranges and channels, used as expression it evaluates to an array of the values of the iterations");
+        m.insert("match", "match value { pattern => result, ... } evaluates the first arm whose pattern matches, patterns are values, ranges like 1..10, types like int x, [first, ...rest], {key, other: pattern} and _, an arm may have a guard: pattern if cond => result");
+        m.insert(".", "obj.name(args) calls the function name of the dict obj or of its prototypes with obj as self, super.name(args) calls the method of the parent class, values of all types have builtin methods like arr.map(f) or s.split(\",\")");
+        m.insert("__add__", "Classes overload operators with special methods, __add__, __sub__, __mul__, __div__, __mod__ and __pow__ are called on the left operand, __eq__ is used for == and !=, < > <= >= are derived from __lt__ and __eq__, __str__ gives the text for printing, __index__ is called by obj[i] and __call__ by obj(args)");
+        m.insert("in", "x in collection checks whether an array contains x, a dict has the key x, a string the substring x or a range the int x, x not in collection is the opposite");
+        m.insert("break", "Leaves the current loop, break(value) makes the loop return the value");
+        m.insert("continue", "Skips to the next iteration of the current loop");
         m.insert("help", "shows this help or shows the help of a given command");
+        m.insert("throw", "Raises a UserError with the given message and an optional payload, which can be caught with try { } catch e { }");
+        m.insert("error", "Raises an error with the given message, same as throw");
+        m.insert("error_kind", "Returns the kind of an error, e.g. TypeError, IndexError or UserError");
+        m.insert("error_message", "Returns the message of an error");
+        m.insert("error_payload", "Returns the payload an error was thrown with or none");
+
+        //Threads
+        m.insert("await", "Waits for a task started with async, or an array of tasks, and returns its result");
+        m.insert("tasks", "Returns all tasks which are still running");
+        m.insert("channel", "Creates a channel to send values between tasks, for x in channel { } receives until it is closed");
+        m.insert("send", "Sends a value through a channel");
+        m.insert("recv", "Waits for the next value of a channel");
+        m.insert("try_recv", "Returns the next value of a channel or none if there is no value yet");
+        m.insert("recv_timeout", "Waits up to x seconds for the next value of a channel, returns none on timeout");
+        m.insert("close", "Closes a channel, values which were already sent can still be received");
 
         //time
         m.insert("wait", "Waits for x seconds");
@@ -1,6 +1,6 @@
 use crate::context::Context;
 use crate::interpreter::Rule;
-use crate::result::Result;
+use crate::result::{ErrorKind, Result};
 use crate::runtime::Runtime;
 use crate::ast::Node;
 use std::collections::HashMap;
@@ -20,6 +20,10 @@ mod util;
 mod expr;
 #[path = "pipe.rs"]
 mod pipe;
+#[path = "pattern.rs"]
+mod pattern;
+#[path = "method.rs"]
+mod method;
 
 use anyhow;
 use dirs;
@@ -30,13 +34,35 @@ use block::*;
 use literal::*;
 use expr::*;
 use pipe::*;
+use pattern::*;
+use method::*;
 
 
+/// Evaluates a whole program, a return leaves the program with its value
+pub fn eval_program(rule: &Node, runtime: Arc<Runtime>, ctx: Arc<Context>) -> Result {
+    return function_result(eval(rule, runtime, ctx));
+}
+
 pub fn eval_runtime_function(name: &str, runtime: Arc<Runtime>) -> Result{
-    return call_function(name, Vec::new().iter(), runtime.clone(), runtime.basectx.clone());
+    return call_function(name, Vec::new().iter(), None, runtime.clone(), runtime.basectx.clone());
+}
+
+pub fn display_runtime_value(value: Result, runtime: Arc<Runtime>) -> Result{
+    return display(value, runtime.clone(), runtime.basectx.clone());
 }
 
+/// Evaluates a node, errors without a location are located at the node
 pub fn eval(rule: &Node, runtime: Arc<Runtime>, ctx: Arc<Context>) -> Result {
+    let mut res = eval_node(rule, runtime, ctx);
+    if let Result::Error(e) = &mut res {
+        if let None = e.location {
+            e.location = Some(rule.location());
+        }
+    }
+    return res;
+}
+
+fn eval_node(rule: &Node, runtime: Arc<Runtime>, ctx: Arc<Context>) -> Result {
     let val = rule.content();
     match rule.rule {
         Rule::Ident => {
@@ -45,11 +71,14 @@ pub fn eval(rule: &Node, runtime: Arc<Runtime>, ctx: Arc<Context>) -> Result {
         Rule::Literal | Rule::Term => {
             return eval(rule.inner().first().unwrap(), runtime, ctx);
         }
+        Rule::AsyncExpr => {
+            return spawn_task(rule.inner().get(1).unwrap(), runtime, ctx);
+        }
         Rule::Pipe => {
             return eval_pipe(rule.inner(), runtime, ctx);
         }
         Rule::Function => {
-            return eval_function(rule.inner(), runtime, ctx);
+            return eval_function(rule.inner(), ctx);
         }
         Rule::String => {
             return eval_string(rule.inner(), runtime, ctx);
@@ -58,7 +87,10 @@ pub fn eval(rule: &Node, runtime: Arc<Runtime>, ctx: Arc<Context>) -> Result {
             return get_home();
         }
         Rule::Int => {
-            return Result::Int(val.parse::<i64>().unwrap());
+            if let Ok(i) = val.parse::<i64>() {
+                return Result::Int(i);
+            }
+            return Result::error(ErrorKind::ParseError, "Int ".to_string() + val + " does not fit into 64 bits");
         }
         Rule::Bool => {
             return Result::Bool(val.parse::<bool>().unwrap());
@@ -90,127 +122,260 @@ pub fn eval(rule: &Node, runtime: Arc<Runtime>, ctx: Arc<Context>) -> Result {
         Rule::Statement => {
             return eval_statement(rule.inner(), runtime, ctx);
         }
-        Rule::Range => {
-            return eval_range(rule.inner(), runtime, ctx);
+        Rule::SliceFrom => {
+            return eval_slice(rule.inner().first(), None, false, runtime, ctx);
+        }
+        Rule::SliceTo => {
+            let inclusive = rule.inner()[0].content() == "..=";
+            return eval_slice(None, rule.inner().get(1), inclusive, runtime, ctx);
         }
         Rule::ForLoop => {
-            return eval_forloop(rule.inner(), runtime, ctx);
+            return eval_forloop(rule.inner(), false, runtime, ctx);
         }
         Rule::WhileLoop => {
-            return eval_whileloop(rule.inner(), runtime, ctx);
+            return eval_whileloop(rule.inner(), false, runtime, ctx);
+        }
+        Rule::LoopExpr => {
+            let sub = rule.inner().first().unwrap();
+            if let Rule::ForLoop = sub.rule() {
+                return eval_forloop(sub.inner(), true, runtime, ctx);
+            }
+            return eval_whileloop(sub.inner(), true, runtime, ctx);
         }
         Rule::Conditional => {
             return eval_conditional(rule.inner(), runtime, ctx);
         }
+        Rule::Match => {
+            return eval_match(rule.inner(), runtime, ctx);
+        }
+        Rule::TryCatch => {
+            return eval_trycatch(rule.inner(), runtime, ctx);
+        }
         Rule::Assignment => {
             return eval_assignment(rule.inner(), runtime, ctx);
         }
+        Rule::Declaration => {
+            return eval_declaration(rule.inner(), runtime, ctx);
+        }
+        Rule::Destructuring => {
+            return eval_destructuring(rule.inner(), runtime, ctx);
+        }
         Rule::Call => {
             return eval_call(rule.inner(), runtime, ctx);
         }
+        Rule::Break => {
+            return eval_break(rule.inner(), runtime, ctx);
+        }
+        Rule::Continue => {
+            return Result::Continue;
+        }
         Rule::LineComment => {
             return Result::None;
         }
+        Rule::KeywordArgument => {
+            return Result::error(ErrorKind::ArgumentError, "Keyword argument ".to_string() + rule.inner()[0].content() + " can only be passed to functions defined with ->");
+        }
         _ => {
-            return Result::Error("Not implemented yet: ".to_string() + rule.content());
+            return Result::error(ErrorKind::RuntimeError, "Not implemented yet: ".to_string() + rule.content());
         }
     }
 }
 
 
-pub fn eval_assignment(inner: &Vec<Node>, runtime: Arc<Runtime>, ctx: Arc<Context>) -> Result {
-    let mut iter = inner.iter();
-    let var = iter.next().unwrap().content();
-    let next = iter.next().unwrap();
-    if let Rule::GetIndex = next.rule() {
-        return eval_assignindex(
-            var,
-            eval(next.inner().first().unwrap(), runtime.clone(), ctx.clone()),
-            iter.next().unwrap().content().as_str(),
-            iter.next(),
-            runtime.clone(),
-            ctx.clone(),
-        );
-    }
-    let op = next.content().as_str();
-    let expr = iter.next();
-    let val: Result;
+/// Applies an assignment operator to the old value of a variable or index
+fn assignment_value(
+    op: &str,
+    old: impl Fn() -> Result,
+    expr: Option<&Node>,
+    runtime: Arc<Runtime>,
+    ctx: Arc<Context>,
+) -> Result {
     match op {
         "=" => {
-            val = eval(expr.unwrap(), runtime, ctx.clone());
-        }
-        "*=" => {
-            val = multiply(ctx.var(var), eval(expr.unwrap(), runtime, ctx.clone()));
-        }
-        "/=" => {
-            val = divide(ctx.var(var), eval(expr.unwrap(), runtime, ctx.clone()));
-        }
-        "+=" => {
-            val = add(ctx.var(var), eval(expr.unwrap(), runtime, ctx.clone()));
+            return eval(expr.unwrap(), runtime, ctx);
         }
-        "-=" => {
-            val = subtract(ctx.var(var), eval(expr.unwrap(), runtime, ctx.clone()));
+        "*=" | "/=" | "+=" | "-=" => {
+            let rhs = eval(expr.unwrap(), runtime.clone(), ctx.clone());
+            if rhs.is_control_flow() {
+                return rhs;
+            }
+            return apply_operator(&op[..1], old(), rhs, runtime, ctx);
         }
         "++" => {
-            val = add(ctx.var(var), Result::Int(1));
+            return apply_operator("+", old(), Result::Int(1), runtime, ctx);
         }
         "--" => {
-            val = subtract(ctx.var(var), Result::Int(1));
+            return apply_operator("-", old(), Result::Int(1), runtime, ctx);
         }
         _ => {
-            return Result::Error("Unknown assignment operator ".to_string() + op);
+            return Result::error(ErrorKind::RuntimeError, "Unknown assignment operator ".to_string() + op);
+        }
+    }
+}
+
+pub fn eval_assignment(inner: &Vec<Node>, runtime: Arc<Runtime>, ctx: Arc<Context>) -> Result {
+    let mut iter = inner.iter().peekable();
+    let mut var = iter.next().unwrap().content().as_str();
+    let mut indices = Vec::new();
+    // d::a::b is assigned like d["a"]["b"], so missing keys are reported
+    if !var.starts_with("$") {
+        let mut parts = var.split("::");
+        var = parts.next().unwrap();
+        for part in parts {
+            indices.push(Result::String(part.to_string()));
         }
     }
-    ctx.set_var(var, val);
+    while let Some(next) = iter.peek() {
+        match next.rule() {
+            Rule::GetIndex => {
+                let index = eval(next.inner().first().unwrap(), runtime.clone(), ctx.clone());
+                if let Result::Error(e) = index {
+                    return Result::Error(e);
+                }
+                if index.is_control_flow() {
+                    return index;
+                }
+                indices.push(index);
+            }
+            Rule::KeyAccess => {
+                indices.push(Result::String(next.content()[2..].to_string()));
+            }
+            _ => {
+                break;
+            }
+        }
+        iter.next();
+    }
+    let op = iter.next().unwrap().content().as_str();
+    let expr = iter.next();
+    if !indices.is_empty() {
+        return eval_assignindex(var, indices, op, expr, runtime, ctx);
+    }
+    let val = assignment_value(op, || ctx.var(var), expr, runtime, ctx.clone());
+    if val.is_control_flow() {
+        return val;
+    }
+    // The error is still assigned, but also returned, so try blocks can catch it
+    let res = match &val {
+        Result::Error(e) => Result::Error(e.clone()),
+        _ => Result::None,
+    };
+    if let Result::Error(e) = ctx.set_var(var, val) {
+        return Result::Error(e);
+    }
+    return res;
+}
+
+/// let and const declare the variable in the current block, a const can't be reassigned
+pub fn eval_declaration(inner: &Vec<Node>, runtime: Arc<Runtime>, ctx: Arc<Context>) -> Result {
+    let constant = inner[0].content() == "const";
+    let var = inner[1].content();
+    let val = eval(&inner[2], runtime.clone(), ctx.clone());
+    if val.is_control_flow() {
+        return val;
+    }
+    if inner[1].rule != Rule::Ident {
+        let mut bindings = Vec::new();
+        if let Result::Error(e) = destructure(&inner[1], val, &mut bindings, runtime, ctx.clone()) {
+            return Result::Error(e);
+        }
+        for (name, value) in bindings {
+            if let Result::Error(e) = ctx.declare_var(&name, value, constant) {
+                return Result::Error(e);
+            }
+        }
+        return Result::None;
+    }
+    if let Result::Error(e) = ctx.declare_var(var, val.clone(), constant) {
+        return Result::Error(e);
+    }
+    if let Result::Error(e) = val {
+        return Result::Error(e);
+    }
     return Result::None;
 }
 
+/// [a, ...rest] = array and {a, b} = dict assign every variable of the pattern
+pub fn eval_destructuring(inner: &Vec<Node>, runtime: Arc<Runtime>, ctx: Arc<Context>) -> Result {
+    let val = eval(&inner[1], runtime.clone(), ctx.clone());
+    if val.is_control_flow() {
+        return val;
+    }
+    let mut bindings = Vec::new();
+    if let Result::Error(e) = destructure(&inner[0], val, &mut bindings, runtime, ctx.clone()) {
+        return Result::Error(e);
+    }
+    for (name, value) in bindings {
+        if let Result::Error(e) = ctx.set_var(&name, value) {
+            return Result::Error(e);
+        }
+    }
+    return Result::None;
+}
 
+/// Assigns to the last index of a chain like a[1]["x"]::y, every index before it has to exist
 pub fn eval_assignindex(
     var: &str,
-    index: Result,
+    mut indices: Vec<Result>,
     op: &str,
     expr: Option<&Node>,
     runtime: Arc<Runtime>,
     ctx: Arc<Context>,
 ) -> Result {
-    let val = ctx.var(var);
-    if let Result::Error(e) = val {
-        return Result::Error(e);
+    let mut container = ctx.var(var);
+    let mut path = var.to_string();
+    let last = indices.pop().unwrap();
+    for index in indices {
+        if let Result::Error(e) = &container {
+            return Result::Error(e.clone());
+        }
+        container = get_index(&container, index.clone());
+        if let Result::Error(mut e) = container {
+            e.message = e.message + " in " + &path;
+            return Result::Error(e);
+        }
+        if let Result::String(key) = &index {
+            path = path + "[\"" + key + "\"]";
+        } else {
+            path = path + "[" + &index.to_string() + "]";
+        }
     }
-    let indexval = get_index(&val, index.clone());
-    if let Result::Error(e) = indexval {
+    if let Result::Error(e) = container {
         return Result::Error(e);
     }
-    let newval: Result;
-    match op {
-        "=" => {
-            newval = eval(expr.unwrap(), runtime, ctx.clone());
-        }
-        "*=" => {
-            newval = multiply(indexval, eval(expr.unwrap(), runtime, ctx.clone()));
-        }
-        "/=" => {
-            newval = divide(indexval, eval(expr.unwrap(), runtime, ctx.clone()));
-        }
-        "+=" => {
-            newval = add(indexval, eval(expr.unwrap(), runtime, ctx.clone()));
-        }
-        "-=" => {
-            newval = subtract(indexval, eval(expr.unwrap(), runtime, ctx.clone()));
-        }
-        "++" => {
-            newval = add(indexval, Result::Int(1));
-        }
-        "--" => {
-            newval = subtract(indexval, Result::Int(1));
-        }
-        _ => {
-            return Result::Error("Unknown assignment operator ".to_string() + op);
+    let mut old = Result::None;
+    if op != "=" {
+        old = get_index(&container, last.clone());
+        if let Result::Error(mut e) = old {
+            e.message = e.message + " in " + &path;
+            return Result::Error(e);
         }
     }
-    ctx.set_var(var, set_index(val, index, newval));
-    return Result::None;
+    let newval = assignment_value(op, || old.clone(), expr, runtime, ctx.clone());
+    if newval.is_control_flow() {
+        return newval;
+    }
+    let res = match &newval {
+        Result::Error(e) => Result::Error(e.clone()),
+        _ => Result::None,
+    };
+    // Arrays and dicts are changed in place, every variable sharing them sees the new value
+    if let Result::Error(mut e) = set_index(container, last, newval) {
+        e.message = e.message + " in " + &path;
+        return Result::Error(e);
+    }
+    return res;
+}
+
+/// Runs the node on a new thread and returns its task
+pub fn spawn_task(pair: &Node, runtime: Arc<Runtime>, ctx: Arc<Context>) -> Result {
+    let node = pair.clone();
+    let newruntime = Arc::clone(&runtime);
+    let newctx = Arc::new(Context::from_parent(ctx.clone(), ctx.me()));
+    let handle = thread::spawn(move || {
+        return function_result(eval_statementitem(&node, newruntime, newctx));
+    });
+    return Result::Task(runtime.add_task(handle));
 }
 
 pub fn eval_statement(inner: &Vec<Node>, runtime: Arc<Runtime>, ctx: Arc<Context>) -> Result {
@@ -224,10 +389,7 @@ pub fn eval_statement(inner: &Vec<Node>, runtime: Arc<Runtime>, ctx: Arc<Context
         pair = first;
     }
     if isasync {
-        let node = pair.clone();
-        let newruntime = Arc::clone(&runtime);
-        let newctx = Arc::new(Context::from_parent(&*ctx, ctx.node));
-        return Result::Error("Threads are not implemented yet".to_string());
+        return spawn_task(pair, runtime, ctx);
     } else {
         return eval_statementitem(pair, runtime, ctx);
     }
@@ -247,3 +409,32 @@ fn get_call(unary_expr: &Node) -> anyhow::Result<&Node> {
     }
     return Err(anyhow::anyhow!(""));
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::result::ErrorKind;
+    use crate::runtime::test::*;
+
+    #[test]
+    fn int_literals_out_of_range() {
+        assert_eq!(text("9223372036854775807"), "9223372036854775807");
+        assert_eq!(error_kind("99999999999999999999"), Some(ErrorKind::ParseError));
+    }
+
+    #[test]
+    fn nested_index_assignment() {
+        assert_eq!(text("a = [{\"x\": 1}]; a[0][\"x\"] = 5; a"), "[ { x: 5 } ]");
+        assert_eq!(text("a = [[1, 2]]; a[0][1] *= 10; a"), "[ [ 1, 20 ] ]");
+        assert_eq!(text("d = {\"k\": [1, 2, 3]}; d::k[2] += 1; d"), "{ k: [ 1, 2, 4 ] }");
+        assert_eq!(text("d = {\"a\": {}}; d::a::b = 2; d"), "{ a: { b: 2 } }");
+        assert_eq!(text("d = {\"a\": [1]}; d::a[0]++; d"), "{ a: [ 2 ] }");
+    }
+
+    #[test]
+    fn missing_intermediate_values() {
+        assert_eq!(error_kind("d = {}; d[\"a\"][\"b\"] = 1"), Some(ErrorKind::KeyError));
+        assert_eq!(error_kind("d = {\"a\": 1}; d::x::y = 1"), Some(ErrorKind::KeyError));
+        assert_eq!(error_kind("a = [[1]]; a[3][0] = 1"), Some(ErrorKind::IndexError));
+        assert_eq!(error_kind("a = [1]; a[0][0] = 1"), Some(ErrorKind::TypeError));
+    }
+}
@@ -2,156 +2,407 @@ use super::*;
 use crate::ast::Node;
 use crate::context::Context;
 use crate::interpreter::Rule;
-use crate::result::Result;
+use crate::result::{ErrorKind, Result};
 use crate::runtime::Runtime;
 
-pub fn eval_unary(inner: &Vec<Node>, runtime: Arc<Runtime>, ctx: Arc<Context>) -> Result {
-    let mut iter = inner.iter();
-    let first = iter.next().unwrap();
-    match first.rule() {
-        Rule::UnaryLOp => {
-            return match_unarylop(first, iter.next().unwrap(), runtime, ctx);
-        }
-        Rule::Term => {
-            let second = iter.next();
-            if let Some(second) = second {
-                match second.rule() {
-                    Rule::UnaryROp => {
-                        return match_unaryrop(first, second, runtime, ctx);
-                    }
-                    Rule::GetIndex => {
-                        return get_index(
-                            &eval(first, runtime.clone(), ctx.clone()),
-                            eval(second.inner().first().unwrap(), runtime, ctx),
-                        );
+/// Unary operators bind stronger than all infix operators except the power operators
+const PREFIX_PRECEDENCE: usize = 8;
+
+#[derive(PartialEq)]
+pub enum Associativity {
+    Left,
+    Right,
+    /// Comparisons chain, `a < b < c` means `a < b && b < c`, ranges take their step as third operand
+    Chain,
+}
+
+enum Token<'a> {
+    Prefix(&'a str),
+    Operand(&'a Node),
+    Infix(&'a str),
+}
+
+/// Expression tree built by the ExprParser, operands are UnaryExprs without their prefix operators
+enum Operation<'a> {
+    Operand(&'a Node),
+    Prefix(&'a str, Box<Operation<'a>>),
+    Infix(&'a str, Box<Operation<'a>>, Box<Operation<'a>>),
+    Chain(Vec<&'a str>, Vec<Operation<'a>>),
+}
+
+/// Pratt parser over the flat operator / operand list of an Expr
+struct ExprParser<'a> {
+    tokens: Vec<Token<'a>>,
+    pos: usize,
+}
+
+impl<'a> ExprParser<'a> {
+    fn new(rules: &'a Vec<Node>) -> ExprParser<'a> {
+        let mut tokens = Vec::new();
+        for pair in rules {
+            match pair.rule() {
+                Rule::Operator => {
+                    let op = pair.content().as_str();
+                    // not in may be written with several spaces
+                    tokens.push(Token::Infix(if op.starts_with("not") { "not in" } else { op }));
+                }
+                Rule::UnaryExpr => {
+                    for inner in pair.inner() {
+                        if let Rule::UnaryLOp = inner.rule() {
+                            tokens.push(Token::Prefix(inner.content().as_str()));
+                        } else {
+                            break;
+                        }
                     }
-                    Rule::ChainedCall => {
-                        return eval_chainedcall(
-                            &eval(first, runtime.clone(), ctx.clone()),
-                            &mut second.inner().iter(),
-                            runtime,
-                            ctx,
-                        );
+                    tokens.push(Token::Operand(pair));
+                }
+                _ => {}
+            }
+        }
+        return ExprParser {
+            tokens: tokens,
+            pos: 0,
+        };
+    }
+
+    fn parse(&mut self, minprecedence: usize) -> Operation<'a> {
+        let mut lhs = match self.tokens.get(self.pos) {
+            Some(&Token::Prefix(op)) => {
+                self.pos += 1;
+                Operation::Prefix(op, Box::new(self.parse(PREFIX_PRECEDENCE)))
+            }
+            Some(&Token::Operand(node)) => {
+                self.pos += 1;
+                Operation::Operand(node)
+            }
+            // The grammar guarantees that an operand follows every operator
+            _ => unreachable!(),
+        };
+        while let Some(&Token::Infix(op)) = self.tokens.get(self.pos) {
+            let (precedence, associativity) = operator_precedence(op);
+            if precedence < minprecedence {
+                break;
+            }
+            self.pos += 1;
+            match associativity {
+                Associativity::Left => {
+                    let rhs = self.parse(precedence + 1);
+                    lhs = Operation::Infix(op, Box::new(lhs), Box::new(rhs));
+                }
+                Associativity::Right => {
+                    let rhs = self.parse(precedence);
+                    lhs = Operation::Infix(op, Box::new(lhs), Box::new(rhs));
+                }
+                Associativity::Chain => {
+                    let mut ops = vec![op];
+                    let mut operands = vec![lhs, self.parse(precedence + 1)];
+                    while let Some(&Token::Infix(next)) = self.tokens.get(self.pos) {
+                        if operator_precedence(next) != (precedence, Associativity::Chain) {
+                            break;
+                        }
+                        self.pos += 1;
+                        ops.push(next);
+                        operands.push(self.parse(precedence + 1));
                     }
-                    _ => {
-                        return Result::Error("Rule::Term followed by unknown Rule".to_string());
+                    if ops.len() == 1 {
+                        let rhs = operands.pop().unwrap();
+                        lhs = Operation::Infix(op, Box::new(operands.pop().unwrap()), Box::new(rhs));
+                    } else {
+                        lhs = Operation::Chain(ops, operands);
                     }
                 }
-            } else {
-                return eval(first, runtime, ctx);
             }
         }
-        _ => {
-            return eval(first, runtime, ctx);
+        return lhs;
+    }
+}
+
+/// Evaluates a single UnaryExpr, prefix operators are applied after the postfix operators
+pub fn eval_unary(inner: &Vec<Node>, runtime: Arc<Runtime>, ctx: Arc<Context>) -> Result {
+    let mut res = eval_postfix(inner, runtime, ctx);
+    if res.is_control_flow() {
+        return res;
+    }
+    for node in inner.iter().rev() {
+        if let Rule::UnaryLOp = node.rule() {
+            res = apply_prefix(node.content().as_str(), res);
         }
     }
+    return res;
 }
 
-pub fn match_unarylop(first: &Node, second: &Node, runtime: Arc<Runtime>, ctx: Arc<Context>) -> Result {
-    match first.content().as_str() {
-        "+" => {
-            return eval(second, runtime, ctx);
+/// Evaluates the Term of a UnaryExpr and applies indexing, calls and postfix operators from left to right
+fn eval_postfix(inner: &Vec<Node>, runtime: Arc<Runtime>, ctx: Arc<Context>) -> Result {
+    let mut iter = inner.iter().skip_while(|node| node.rule == Rule::UnaryLOp);
+    let term = iter.next().unwrap();
+    let mut res = eval(term, runtime.clone(), ctx.clone());
+    if res.is_control_flow() {
+        return res;
+    }
+    // Functions are named by the variable they are called through in stack traces
+    let mut name = "<anonymous>";
+    if let Some(var) = term.inner().first() {
+        if let Rule::Var = var.rule() {
+            name = var.inner().first().unwrap().content().as_str();
+        }
+    }
+    let mut through_super = name == "super";
+    for postfix in iter {
+        match postfix.rule() {
+            Rule::GetIndex => {
+                let index = eval(postfix.inner().first().unwrap(), runtime.clone(), ctx.clone());
+                if index.is_control_flow() {
+                    return index;
+                }
+                if let Some(value) = call_special(&res, "__index__", (vec![index.clone()], Vec::new()), Some(postfix), runtime.clone(), ctx.clone()) {
+                    res = value;
+                } else {
+                    res = get_index(&res, index);
+                }
+            }
+            Rule::KeyAccess => {
+                res = get_index(&res, Result::String(postfix.content()[2..].to_string()));
+            }
+            Rule::ChainedCall => {
+                res = eval_chainedcall(
+                    &res,
+                    &mut postfix.inner().iter(),
+                    name,
+                    Some(postfix),
+                    runtime.clone(),
+                    ctx.clone(),
+                );
+            }
+            Rule::MethodCall => {
+                res = eval_methodcall(res, postfix, through_super, runtime.clone(), ctx.clone());
+            }
+            Rule::UnaryROp => {
+                res = apply_postfix(postfix.content().as_str(), res);
+            }
+            _ => {
+                return Result::error(ErrorKind::RuntimeError, "Rule::Term followed by unknown Rule".to_string());
+            }
+        }
+        if res.is_control_flow() {
+            return res;
         }
+        name = "<anonymous>";
+        through_super = false;
+    }
+    return res;
+}
+
+fn apply_prefix(op: &str, value: Result) -> Result {
+    match op {
         "-" => {
-            return multiply(eval(second, runtime, ctx), Result::Int(-1));
+            return minus(value);
         }
         "!" => {
-            return negate(eval(second, runtime, ctx));
+            return negate(value);
         }
         _ => {
-            return eval(second, runtime, ctx);
+            return value;
         }
     }
 }
 
-pub fn match_unaryrop(first: &Node, second: &Node, runtime: Arc<Runtime>, ctx: Arc<Context>) -> Result {
-    match second.content().as_str() {
+fn apply_postfix(op: &str, value: Result) -> Result {
+    match op {
         "!" => {
-            // TODO
-            return faculty(eval(first, runtime, ctx));
+            return faculty(value);
         }
         _ => {
-            return eval(first, runtime, ctx);
+            return value;
         }
     }
 }
 
-pub fn operator_precedence(op: &str) -> usize {
+/// Precedence and associativity of an infix operator, higher values bind stronger.
+/// == and != bind looser than the other comparisons and don't chain, `a < b == c < d` compares the results of both comparisons
+pub fn operator_precedence(op: &str) -> (usize, Associativity) {
     match op {
-        "==" => {
-            return 0;
+        "||" | "or" => {
+            return (1, Associativity::Left);
+        }
+        "&&" | "and" => {
+            return (2, Associativity::Left);
+        }
+        "==" | "!=" => {
+            return (3, Associativity::Left);
         }
-        "|" => {
-            return 1;
+        "<=" | ">=" | "<" | ">" | "in" | "not in" => {
+            return (4, Associativity::Chain);
         }
-        "<=" | ">=" | "<" | ">" => {
-            return 2;
+        ".." | "..=" => {
+            return (5, Associativity::Chain);
         }
         "+" | "-" => {
-            return 3;
+            return (6, Associativity::Left);
         }
         "*" | "/" | "%" => {
-            return 4;
+            return (7, Associativity::Left);
         }
         "**" | "//" | "^" => {
-            return 5;
+            return (9, Associativity::Right);
         }
         _ => {
-            return 5;
+            return (9, Associativity::Left);
         }
     }
 }
 
 pub fn eval_expr(rules: &Vec<Node>, runtime: Arc<Runtime>, ctx: Arc<Context>) -> Result {
-    const MAXPRECEDENCE: usize = 6;
-    let mut operatorprecedence: [Vec<usize>; MAXPRECEDENCE] = [
-        Vec::new(),
-        Vec::new(),
-        Vec::new(),
-        Vec::new(),
-        Vec::new(),
-        Vec::new(),
-    ];
-    let mut operators = Vec::<&str>::new();
-    let mut results: Vec<Result> = Vec::new();
-
-    for pair in rules {
-        let rule = pair.rule();
-        match rule {
-            Rule::Operator => {
-                let mut n = operators.len();
-                let op = pair.content().as_str();
-                let precedence = operator_precedence(op);
-                for j in precedence..MAXPRECEDENCE {
-                    n -= operatorprecedence[j].len();
+    let mut parser = ExprParser::new(rules);
+    let operation = parser.parse(0);
+    if let Some(ternary) = rules.last().filter(|node| node.rule == Rule::Ternary) {
+        return eval_ternary(&operation, ternary.inner(), runtime, ctx);
+    }
+    return eval_operation(&operation, runtime, ctx);
+}
+
+/// cond ? a : b, only the chosen side is evaluated
+fn eval_ternary(condition: &Operation, sides: &Vec<Node>, runtime: Arc<Runtime>, ctx: Arc<Context>) -> Result {
+    match eval_operation(condition, runtime.clone(), ctx.clone()) {
+        Result::Bool(true) => {
+            return eval(&sides[0], runtime, ctx);
+        }
+        Result::Bool(false) => {
+            return eval(&sides[1], runtime, ctx);
+        }
+        Result::Error(e) => {
+            return Result::Error(e);
+        }
+        other if other.is_control_flow() => {
+            return other;
+        }
+        other => {
+            return Result::error(ErrorKind::TypeError, "The condition of ?: needs to be a bool, not ".to_string() + &other.typename());
+        }
+    }
+}
+
+fn eval_operation(operation: &Operation, runtime: Arc<Runtime>, ctx: Arc<Context>) -> Result {
+    match operation {
+        Operation::Operand(node) => {
+            return eval_postfix(node.inner(), runtime, ctx);
+        }
+        Operation::Prefix(op, operand) => {
+            let value = eval_operation(operand, runtime, ctx);
+            if value.is_control_flow() {
+                return value;
+            }
+            return apply_prefix(op, value);
+        }
+        Operation::Infix(op, lhs, rhs) => match *op {
+            "||" | "or" => {
+                return eval_logical(true, lhs, rhs, runtime, ctx);
+            }
+            "&&" | "and" => {
+                return eval_logical(false, lhs, rhs, runtime, ctx);
+            }
+            _ => {
+                let lhs = eval_operation(lhs, runtime.clone(), ctx.clone());
+                if lhs.is_control_flow() {
+                    return lhs;
                 }
-                operatorprecedence[precedence].push(n);
-                operators.push(op);
+                let rhs = eval_operation(rhs, runtime.clone(), ctx.clone());
+                return apply_operator(op, lhs, rhs, runtime, ctx);
             }
-            Rule::UnaryExpr => {
-                results.push(eval_unary(pair.inner(), runtime.clone(),ctx.clone()));
+        },
+        Operation::Chain(ops, operands) if ops[0].starts_with("..") => {
+            if operands.len() > 3 || ops[1] != ".." {
+                return Result::error(ErrorKind::ValueError, "Range may only have a start, an end and a step".to_string());
+            }
+            let mut bounds = Vec::new();
+            for operand in operands {
+                let bound = eval_operation(operand, runtime.clone(), ctx.clone());
+                if bound.is_control_flow() {
+                    return bound;
+                }
+                bounds.push(bound);
             }
-            _ => {}
+            let (step, end, start) = (bounds.pop().unwrap(), bounds.pop().unwrap(), bounds.pop().unwrap());
+            return make_range(start, end, step, ops[0] == "..=");
+        }
+        Operation::Chain(ops, operands) => {
+            let mut lhs = eval_operation(&operands[0], runtime.clone(), ctx.clone());
+            if lhs.is_control_flow() {
+                return lhs;
+            }
+            for (i, op) in ops.iter().enumerate() {
+                let rhs = eval_operation(&operands[i + 1], runtime.clone(), ctx.clone());
+                if rhs.is_control_flow() {
+                    return rhs;
+                }
+                let res = apply_operator(op, lhs, rhs.clone(), runtime.clone(), ctx.clone());
+                if let Result::Bool(true) = res {
+                    lhs = rhs;
+                } else {
+                    return res;
+                }
+            }
+            return Result::Bool(true);
         }
     }
+}
 
-    let mut i = MAXPRECEDENCE - 1;
-    loop {
-        let vec = &operatorprecedence[i];
-        for j in vec.iter() {
-            let op = operators.remove(*j);
-            let lhs = results.remove(*j);
-            let rhs = results.remove(*j);
+/// || stops at the first true value, && at the first false value, the right side is only evaluated if it can change the result
+fn eval_logical(
+    shortcircuit: bool,
+    lhs: &Operation,
+    rhs: &Operation,
+    runtime: Arc<Runtime>,
+    ctx: Arc<Context>,
+) -> Result {
+    let mut res = eval_operation(lhs, runtime.clone(), ctx.clone());
+    if let Result::Bool(b) = res {
+        if b == shortcircuit {
+            return res;
+        }
+        res = eval_operation(rhs, runtime, ctx);
+    }
+    match res {
+        Result::Bool(_) | Result::Error(_) | Result::Return(_) | Result::Break(_) | Result::Continue => {
+            return res;
+        }
+        _ => {
+            return Result::error(ErrorKind::TypeError, "Logical operators may only be used with bools".to_string());
+        }
+    }
+}
 
-            results.insert(*j, make_result(op, lhs, rhs));
+/// Applies an infix operator, operators overloaded by objects call their special methods.
+/// An error operand is the result of every operator, so comparisons don't turn it into false, the same goes for return, break and continue
+pub fn apply_operator(op: &str, lhs: Result, rhs: Result, runtime: Arc<Runtime>, ctx: Arc<Context>) -> Result {
+    if let Result::Error(_) = lhs {
+        return lhs;
+    }
+    if let Result::Error(_) = rhs {
+        return rhs;
+    }
+    if lhs.is_control_flow() {
+        return lhs;
+    }
+    if rhs.is_control_flow() {
+        return rhs;
+    }
+    if let Some(res) = overload(op, &lhs, &rhs, runtime.clone(), ctx.clone()) {
+        return res;
+    }
+    match op {
+        "==" | "!=" | "<=" | ">=" | "<" | ">" => {
+            return compare_result(op, compare_values(&lhs, &rhs, runtime, ctx));
         }
-        if i == 0 {
-            break;
+        "in" => {
+            return contains_value(&lhs, &rhs, runtime, ctx);
+        }
+        "not in" => {
+            return negate(contains_value(&lhs, &rhs, runtime, ctx));
+        }
+        _ => {
+            return make_result(op, lhs, rhs);
         }
-        i -= 1;
     }
-    let first = results.remove(0);
-    return first;
 }
 
 fn make_result(op: &str, lhs: Result, rhs: Result) -> Result {
@@ -177,23 +428,76 @@ fn make_result(op: &str, lhs: Result, rhs: Result) -> Result {
         "//" => {
             return root(lhs, rhs);
         }
-        "==" => {
-            return equals(&lhs, &rhs);
-        }
-        "<=" => {
-            return smallereq(&lhs, &rhs);
-        }
-        ">=" => {
-            return greatereq(&lhs, &rhs);
-        }
-        "<" => {
-            return smaller(&lhs, &rhs);
-        }
-        ">" => {
-            return greater(&lhs, &rhs);
+        ".." | "..=" => {
+            return make_range(lhs, rhs, Result::Int(1), op == "..=");
         }
         _ => {
-            return Result::Error("Unknown operator ".to_owned() + op);
+            return Result::error(ErrorKind::RuntimeError, "Unknown operator ".to_owned() + op);
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::result::ErrorKind;
+    use crate::runtime::test::*;
+
+    #[test]
+    fn logical_operators_short_circuit() {
+        assert_eq!(text("false && nope()"), "false");
+        assert_eq!(text("true || nope()"), "true");
+        assert_eq!(text("c = 0; f = () -> { c = c + 1; true }; false && f(); true || f(); c"), "0");
+        assert_eq!(text("true and false"), "false");
+        assert_eq!(text("false or true"), "true");
+    }
+
+    #[test]
+    fn logical_operators_need_bools() {
+        assert_eq!(error_kind("1 && true"), Some(ErrorKind::TypeError));
+    }
+
+    #[test]
+    fn unequal() {
+        assert_eq!(text("1 != 2"), "true");
+        assert_eq!(text("\"a\" != \"a\""), "false");
+        assert_eq!(text("[1, 2] != [1, 2]"), "false");
+    }
+
+    #[test]
+    fn precedence() {
+        assert_eq!(text("2 + 3 * 4"), "14");
+        assert_eq!(text("10 - 4 - 3"), "3");
+        assert_eq!(text("2^3^2"), "512");
+        assert_eq!(text("-2**2"), "-4");
+        assert_eq!(text("2 * -3"), "-6");
+    }
+
+    #[test]
+    fn chained_comparisons() {
+        assert_eq!(text("0 < 5 < 10"), "true");
+        assert_eq!(text("0 < 15 < 10"), "false");
+        assert_eq!(text("1 == 1 == true"), "true");
+        assert_eq!(text("1 < 2 == 3 < 4"), "true");
+    }
+
+    #[test]
+    fn negating_the_smallest_int() {
+        assert_eq!(error_kind("m = -9223372036854775807 - 1; -m"), Some(ErrorKind::ArithmeticError));
+    }
+
+    #[test]
+    fn comparisons_pass_errors_on() {
+        assert_eq!(error_kind("[1][5] == 3"), Some(ErrorKind::IndexError));
+        assert_eq!(error_kind("3 < [1][5]"), Some(ErrorKind::IndexError));
+        assert_eq!(error_kind("3 != [1][5]"), Some(ErrorKind::IndexError));
+        assert_eq!(text("1 < \"a\""), "false");
+    }
+
+    #[test]
+    fn ternary() {
+        assert_eq!(text("true ? 1 : 2"), "1");
+        assert_eq!(text("false ? [1][5] : 2"), "2");
+        assert_eq!(text("true ? false ? 1 : 2 : 3"), "2");
+        assert_eq!(error_kind("1 ? 2 : 3"), Some(ErrorKind::TypeError));
+    }
+}
@@ -1,5 +1,5 @@
 use super::*;
-use crate::result::Result;
+use crate::result::{ErrorKind, ErrorValue, Result};
 use std::io::prelude::*;
 use crate::interpreter;
 
@@ -33,12 +33,15 @@ pub fn print_help(args: &Vec<Node>, runtime: Arc<Runtime>, ctx: Arc<Context>) {
     }
 }
 
-pub fn change_dir(node: Option<&Node>, runtime: Arc<Runtime>, ctx: Arc<Context>) {
+pub fn change_dir(node: Option<&Node>, runtime: Arc<Runtime>, ctx: Arc<Context>) -> Result {
     if let Some(node) = node{
         if let Result::String(path) = eval(&node,runtime,ctx){
-            std::env::set_current_dir(&std::path::Path::new(&path));
+            if let Err(e) = std::env::set_current_dir(&std::path::Path::new(&path)){
+                return Result::error(ErrorKind::IOError, "Could not change directory to ".to_string() + &path + ": " + &e.to_string());
+            }
         }
     }
+    return Result::None;
 }
 
 pub fn include_file(node: Option<&Node>, runtime: Arc<Runtime>, ctx: Arc<Context>) -> Result {
@@ -48,38 +51,361 @@ pub fn include_file(node: Option<&Node>, runtime: Arc<Runtime>, ctx: Arc<Context
                 return res;
             }
             else{
-                return Result::Error("File ".to_string() + &pathstring + " could not be interpreted");
+                return Result::error(ErrorKind::IOError, "File ".to_string() + &pathstring + " could not be interpreted");
+            }
+        }
+    }
+    return Result::error(ErrorKind::TypeError, "Only files from String file paths may be included".to_string());
+}
+
+/// Dict bound to the identifier of a catch block
+pub fn error_dict(e: &ErrorValue) -> Result {
+    let mut map = HashMap::new();
+    map.insert("message".to_string(), Result::String(e.message.clone()));
+    map.insert("kind".to_string(), Result::String(e.kind.name()));
+    if let Some(payload) = &e.payload {
+        map.insert("payload".to_string(), *payload.clone());
+    }
+    if let Some(location) = &e.location {
+        map.insert("location".to_string(), Result::String(location.to_string()));
+    }
+    return Result::dict(map);
+}
+
+/// Errors and caught error dicts are both treated as errors
+fn as_error(value: Result) -> Option<ErrorValue> {
+    match value {
+        Result::Error(e) => {
+            return Some(e);
+        }
+        Result::Dict(map) if map.lock().unwrap().contains_key("message") => {
+            let map = map.lock().unwrap().clone();
+            let mut kind = ErrorKind::UserError;
+            if let Some(name) = map.get("kind") {
+                kind = ErrorKind::from_name(&name.to_string());
+            }
+            return Some(ErrorValue {
+                kind: kind,
+                message: map["message"].to_string(),
+                location: None,
+                payload: map.get("payload").map(|payload| Box::new(payload.clone())),
+                trace: Vec::new(),
+            });
+        }
+        _ => {
+            return None;
+        }
+    }
+}
+
+/// Raises a user error with an optional payload, a caught error dict is raised again with its kind
+pub fn throw(
+    node: Option<&Node>,
+    payload: Option<&Node>,
+    runtime: Arc<Runtime>,
+    ctx: Arc<Context>,
+) -> Result {
+    let mut error = ErrorValue {
+        kind: ErrorKind::UserError,
+        message: "".to_string(),
+        location: None,
+        payload: None,
+        trace: Vec::new(),
+    };
+    if let Some(node) = node {
+        let value = eval(node, runtime.clone(), ctx.clone());
+        if let Some(e) = as_error(value.clone()) {
+            error = e;
+        } else {
+            error.message = value.to_string();
+        }
+    }
+    if let Some(payload) = payload {
+        error.payload = Some(Box::new(eval(payload, runtime, ctx)));
+    }
+    return Result::Error(error);
+}
+
+/// Field of an error or a caught error dict, used by error_kind, error_message and error_payload
+pub fn error_field(field: &str, node: Option<&Node>, runtime: Arc<Runtime>, ctx: Arc<Context>) -> Result {
+    if let Some(node) = node {
+        if let Some(e) = as_error(eval(node, runtime, ctx)) {
+            match field {
+                "kind" => {
+                    return Result::String(e.kind.name());
+                }
+                "message" => {
+                    return Result::String(e.message);
+                }
+                _ => {
+                    if let Some(payload) = e.payload {
+                        return *payload;
+                    }
+                    return Result::None;
+                }
+            }
+        }
+    }
+    return Result::error(ErrorKind::TypeError, "error_".to_string() + field + " may only be used with errors");
+}
+
+/// Waits for a task or an array of tasks and returns their results
+pub fn await_task(node: Option<&Node>, runtime: Arc<Runtime>, ctx: Arc<Context>) -> Result {
+    if let Some(node) = node {
+        match eval(node, runtime, ctx) {
+            Result::Task(task) => {
+                return task.join();
+            }
+            Result::Array(tasks) => {
+                let tasks = tasks.lock().unwrap().clone();
+                let mut results = Vec::with_capacity(tasks.len());
+                for task in tasks {
+                    if let Result::Task(task) = task {
+                        results.push(task.join());
+                    } else {
+                        return Result::error(ErrorKind::TypeError, "await may only be used with tasks".to_string());
+                    }
+                }
+                return Result::array(results);
+            }
+            Result::Error(e) => {
+                return Result::Error(e);
+            }
+            _ => {}
+        }
+    }
+    return Result::error(ErrorKind::TypeError, "await may only be used with tasks".to_string());
+}
+
+fn channel_error() -> Result {
+    return Result::error(ErrorKind::TypeError, "Channel functions may only be used with channels".to_string());
+}
+
+pub fn channel_send(channel: Result, value: Result) -> Result {
+    match channel {
+        Result::Channel(channel) => {
+            return channel.send(value);
+        }
+        Result::Error(e) => {
+            return Result::Error(e);
+        }
+        _ => {
+            return channel_error();
+        }
+    }
+}
+
+/// Blocks until a value is received, receiving from a closed and empty channel is an error
+pub fn channel_recv(channel: Result) -> Result {
+    match channel {
+        Result::Channel(channel) => {
+            if let Some(value) = channel.recv() {
+                return value;
+            }
+            return Result::error(ErrorKind::RuntimeError, "Channel is closed".to_string());
+        }
+        Result::Error(e) => {
+            return Result::Error(e);
+        }
+        _ => {
+            return channel_error();
+        }
+    }
+}
+
+/// Returns none if no value is available yet
+pub fn channel_try_recv(channel: Result) -> Result {
+    match channel {
+        Result::Channel(channel) => match channel.try_recv() {
+            Ok(value) => {
+                return value;
+            }
+            Err(std::sync::mpsc::TryRecvError::Empty) => {
+                return Result::None;
+            }
+            Err(std::sync::mpsc::TryRecvError::Disconnected) => {
+                return Result::error(ErrorKind::RuntimeError, "Channel is closed".to_string());
+            }
+        },
+        Result::Error(e) => {
+            return Result::Error(e);
+        }
+        _ => {
+            return channel_error();
+        }
+    }
+}
+
+/// Returns none if no value was received within the given seconds
+pub fn channel_recv_timeout(channel: Result, seconds: Result) -> Result {
+    let timeout = match seconds {
+        Result::Int(i) if i >= 0 => std::time::Duration::from_secs(i as u64),
+        Result::Float(f) if f >= 0.0 => match std::time::Duration::try_from_secs_f64(f) {
+            Ok(timeout) => timeout,
+            Err(_) => {
+                return Result::error(ErrorKind::ValueError, "The timeout ".to_string() + &f.to_string() + " is too long");
             }
+        },
+        Result::Int(_) | Result::Float(_) => {
+            return Result::error(ErrorKind::ValueError, "The timeout needs to be a positive number, not ".to_string() + &seconds.to_string());
+        }
+        Result::Error(e) => {
+            return Result::Error(e);
+        }
+        _ => {
+            return Result::error(ErrorKind::TypeError, "The timeout needs to be an int or float, not ".to_string() + &seconds.typename());
+        }
+    };
+    match channel {
+        Result::Channel(channel) => match channel.recv_timeout(timeout) {
+            Ok(value) => {
+                return value;
+            }
+            Err(std::sync::mpsc::RecvTimeoutError::Timeout) => {
+                return Result::None;
+            }
+            Err(std::sync::mpsc::RecvTimeoutError::Disconnected) => {
+                return Result::error(ErrorKind::RuntimeError, "Channel is closed".to_string());
+            }
+        },
+        Result::Error(e) => {
+            return Result::Error(e);
+        }
+        _ => {
+            return channel_error();
+        }
+    }
+}
+
+pub fn channel_close(channel: Result) -> Result {
+    match channel {
+        Result::Channel(channel) => {
+            channel.close();
+            return Result::None;
+        }
+        Result::Error(e) => {
+            return Result::Error(e);
+        }
+        _ => {
+            return channel_error();
         }
     }
-    return Result::Error("Only files from String file paths may be included".to_string());
 }
 
+/// Calls a callback of a builtin like map, errors get a frame with the name and the call of the builtin
 pub fn exec_func(
-    mut iter: std::slice::Iter<Result>,
+    iter: std::slice::Iter<Result>,
     function: &Result,
+    name: &str,
+    callsite: Option<&Node>,
     runtime: Arc<Runtime>,
     ctx: Arc<Context>,
 ) -> Result {
     if let Result::Function {
         block,
         parameters,
-        vars,
+        env,
     } = function
     {
-        let newctx = Context::from_vars(&*ctx, vars.clone(), Some(function));
-        for param in parameters {
-            if let Some(expr) = iter.next() {
-                newctx.set_own_var(&param.name, expr.clone());
-            } else {
-                if let Some(defaultvalue) = param.defaultvalue.clone() {
-                    newctx.set_own_var(&param.name, defaultvalue);
-                } else {
-                    return Result::Error("Function parameter needs to be passed, or default value needs to be deckared".to_string());
-                }
+        let env = match env.upgrade() {
+            Some(env) => env,
+            None => {
+                return trace_call(closed_env(), name, callsite);
             }
+        };
+        let newctx = Arc::new(Context::from_closure(env, &ctx, Some(Arc::new(function.clone()))));
+        let args = iter.cloned().collect();
+        if let Result::Error(e) = bind_parameters(parameters, args, Vec::new(), false, name, runtime.clone(), newctx.clone()) {
+            return trace_call(Result::Error(e), name, callsite);
         }
-        return eval(&block, runtime, Arc::new(newctx));
+        let res = function_result(eval(block, runtime, newctx));
+        return trace_call(res, name, callsite);
+    }
+    return Result::error(ErrorKind::TypeError, "Is not a function".to_string());
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::result::ErrorKind;
+    use crate::runtime::test::*;
+
+    #[test]
+    fn error_fields() {
+        assert_eq!(text("error_kind([1][3])"), "IndexError");
+        assert_eq!(text("error_message(error(\"x\"))"), "x");
+        assert_eq!(text("try { throw(\"m\", [1, 2]) } catch e { [error_kind(e), error_message(e), error_payload(e)] }"), "[ UserError, m, [ 1, 2 ] ]");
+        assert_eq!(text("type(error(\"x\"))"), "error");
+    }
+
+    #[test]
+    fn error_fields_of_other_values() {
+        assert_eq!(error_kind("error_kind(5)"), Some(ErrorKind::TypeError));
+        assert_eq!(error_kind("error_message()"), Some(ErrorKind::TypeError));
+    }
+
+    #[test]
+    fn rethrown_errors_keep_their_kind() {
+        assert_eq!(error_kind("try { [1][5] } catch e { throw(e) }"), Some(ErrorKind::IndexError));
+    }
+
+    #[test]
+    fn await_tasks() {
+        assert_eq!(text("type(async 1)"), "task");
+        assert_eq!(text("t = async 1 + 2; await(t)"), "3");
+        assert_eq!(text("t = async 1; await(t); await(t)"), "1");
+        assert_eq!(text("await([async 1, async 2])"), "[ 1, 2 ]");
+        assert_eq!(text("f = (n) -> { n * 2 }; await(async f(4))"), "8");
+    }
+
+    #[test]
+    fn tasks_share_variables() {
+        assert_eq!(text("x = 1; t = async { x = 5 }; await(t); x"), "5");
+    }
+
+    #[test]
+    fn running_tasks() {
+        assert_eq!(text("t = async sleep(0.2); len(tasks())"), "1");
+        assert_eq!(text("await([async sleep(0.05)]); len(tasks())"), "0");
+    }
+
+    #[test]
+    fn errors_of_tasks() {
+        assert_eq!(text("t = async [1][5]; error_kind(await(t))"), "IndexError");
+        assert_eq!(error_kind("await(5)"), Some(ErrorKind::TypeError));
+        assert_eq!(error_kind("await()"), Some(ErrorKind::TypeError));
+    }
+
+    #[test]
+    fn channels() {
+        assert_eq!(text("type(channel())"), "channel");
+        assert_eq!(text("c = channel(); send(c, 1); send(c, 2); [recv(c), try_recv(c), try_recv(c)]"), "[ 1, 2,  ]");
+        assert_eq!(text("c = channel(); recv_timeout(c, 0.05)"), "");
+        assert_eq!(
+            text("c = channel(); t = async { for i in 0..3 { send(c, i) }; close(c) }; r = []; for x in c { push(r, x) }; r"),
+            "[ 0, 1, 2 ]"
+        );
+    }
+
+    #[test]
+    fn recv_timeout_while_a_task_waits() {
+        assert_eq!(text("c = channel(); t = async recv(c); sleep(0.05); r = recv_timeout(c, 0.05); send(c, 1); [r, await(t)]"), "[ , 1 ]");
+    }
+
+    #[test]
+    fn timeouts_out_of_range() {
+        assert_eq!(error_kind("c = channel(); recv_timeout(c, 100000000000000000000.0)"), Some(ErrorKind::ValueError));
+        assert_eq!(error_kind("c = channel(); recv_timeout(c, 0.0 - 1.5)"), Some(ErrorKind::ValueError));
+        assert_eq!(error_kind("c = channel(); recv_timeout(c, float(\"nan\"))"), Some(ErrorKind::ValueError));
+        assert_eq!(error_kind("c = channel(); recv_timeout(c, float(\"inf\"))"), Some(ErrorKind::ValueError));
+        assert_eq!(text("c = channel(); send(c, 1); recv_timeout(c, 9223372036854775807)"), "1");
+    }
+
+    #[test]
+    fn errors_of_channels() {
+        assert_eq!(error_kind("c = channel(); close(c); send(c, 1)"), Some(ErrorKind::RuntimeError));
+        assert_eq!(error_kind("c = channel(); close(c); recv(c)"), Some(ErrorKind::RuntimeError));
+        assert_eq!(error_kind("send(5, 1)"), Some(ErrorKind::TypeError));
+        assert_eq!(error_kind("c = channel(); recv_timeout(c, -1)"), Some(ErrorKind::ValueError));
+        assert_eq!(error_kind("c = channel(); recv_timeout(c, \"1\")"), Some(ErrorKind::TypeError));
     }
-    return Result::Error("Is not a function".to_string());
 }
@@ -1,7 +1,8 @@
 use crate::result;
 use crate::context::Context;
 use crate::runtime::Runtime;
-use anyhow::Result;
+use anyhow::{anyhow, Result};
+use pest::error::{Error, ErrorVariant, LineColLocation};
 use pest::Parser;
 use pest_derive::*;
 mod eval;
@@ -14,13 +15,134 @@ struct Language;
 
 pub fn interpret(text: String, runtime: Arc<Runtime>, ctx: Arc<Context>) -> Result<result::Result> {
     //Tokenizer
-    let mut pairs = Language::parse(Rule::Block, text.as_str())?;
-    let ast = build_ast(pairs.next().unwrap());
+    let pairs = match Language::parse(Rule::Program, text.as_str()) {
+        Ok(pairs) => pairs,
+        Err(e) => return Err(anyhow!(format_parse_error(&e, text.as_str()))),
+    };
+    let block = pairs.filter(|pair| pair.as_rule() == Rule::Block).next();
+    if let None = block {
+        return Ok(result::Result::None);
+    }
+    let source = Source::new(runtime.current_file(), text.as_str());
+    let ast = build_ast(block.unwrap(), &source);
     //println!("{:?}",ast);
     //ast.to_string();
-    return Ok(eval::eval(&ast, runtime, ctx));
+    return Ok(eval::eval_program(&ast, runtime, ctx));
 }
 
 pub fn interpret_function(name: &str, runtime: Arc<Runtime>) -> result::Result {
     return eval::eval_runtime_function(name, runtime);
 }
+
+/// Text of a value like println shows it, objects with __str__ decide their own text
+pub fn display_value(value: result::Result, runtime: Arc<Runtime>) -> result::Result {
+    return eval::display_runtime_value(value, runtime);
+}
+
+/// Renders a pest error with the offending line and a caret under the failing position
+fn format_parse_error(error: &Error<Rule>, text: &str) -> String {
+    let (line, column) = match error.line_col {
+        LineColLocation::Pos(pos) => pos,
+        LineColLocation::Span(start, _) => start,
+    };
+    let mut message = format!("Parse error at line {}, column {}", line, column);
+    match &error.variant {
+        ErrorVariant::ParsingError { positives, negatives: _ } => {
+            let mut expected: Vec<&str> = Vec::new();
+            for rule in positives {
+                let name = rule_name(rule);
+                if !expected.contains(&name) {
+                    expected.push(name);
+                }
+            }
+            if let Some(last) = expected.pop() {
+                message += ": expected ";
+                if expected.len() > 0 {
+                    message += &(expected.join(", ") + " or ");
+                }
+                message += last;
+            }
+        }
+        ErrorVariant::CustomError { message: custom } => {
+            message += &(": ".to_string() + custom);
+        }
+    }
+    let source = text.lines().nth(line - 1).unwrap_or("");
+    let caret: String = source
+        .chars()
+        .take(column - 1)
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .collect();
+    let number = line.to_string();
+    message += &format!("\n{} | {}", number, source);
+    message += &format!("\n{} | {}^", " ".repeat(number.len()), caret);
+    return message;
+}
+
+/// Readable name of a rule, used in parse errors
+fn rule_name(rule: &Rule) -> &'static str {
+    match rule {
+        Rule::Expr | Rule::UnaryExpr | Rule::Term | Rule::Literal | Rule::Var | Rule::Call
+        | Rule::Pipe | Rule::Function | Rule::AsyncExpr | Rule::Match
+        | Rule::LoopExpr => "expression",
+        Rule::Ternary => "`?`",
+        Rule::Block | Rule::Statement | Rule::Assignment | Rule::Conditional
+        | Rule::ForLoop | Rule::WhileLoop | Rule::LineComment | Rule::Declaration
+        | Rule::Destructuring => "statement",
+        Rule::Ident => "identifier",
+        Rule::Operator => "operator",
+        Rule::UnaryLOp | Rule::UnaryROp => "unary operator",
+        Rule::ExplizitAssignmentOp | Rule::ImplizitAssignmentOp => "assignment operator",
+        Rule::GetIndex => "index `[`",
+        Rule::KeyAccess => "key `::`",
+        Rule::ChainedCall => "call `(`",
+        Rule::MethodCall | Rule::MethodName => "method call `.`",
+        Rule::Arguments => "arguments",
+        Rule::FunctionParams | Rule::FunctionParam => "parameter",
+        Rule::Pair => "dict entry",
+        Rule::KeywordArgument => "keyword argument",
+        Rule::Variadic => "`...`",
+        Rule::ArrayPattern | Rule::DictPattern | Rule::RestPattern | Rule::KeyPattern | Rule::ValuePattern
+        | Rule::RangePattern | Rule::TypePattern | Rule::Wildcard => "pattern",
+        Rule::MatchArm => "match arm",
+        Rule::MatchGuard => "guard",
+        Rule::Int | Rule::Float => "number",
+        Rule::String => "string",
+        Rule::Escape | Rule::Text | Rule::Interpolation => "string content",
+        Rule::CloseBrace => "closing `}`",
+        Rule::CloseBracket => "closing `]`",
+        Rule::CloseParen => "closing `)`",
+        Rule::Quote => "closing `\"`",
+        Rule::EOI => "end of input",
+        _ => "token",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::runtime::Runtime;
+
+    fn parse_error(script: &str) -> String {
+        return Runtime::new_bare().run(script.to_string()).unwrap_err().to_string();
+    }
+
+    #[test]
+    fn parse_errors_show_the_position() {
+        let message = parse_error("y = 1\nz = (2 +\n");
+        assert!(message.starts_with("Parse error at line 2, column 9: expected expression"), "{}", message);
+        assert!(message.ends_with("2 | z = (2 +\n  |         ^"), "{}", message);
+    }
+
+    #[test]
+    fn parse_errors_name_the_expected_tokens() {
+        let message = parse_error("x = [1, 2");
+        assert!(message.contains("closing `]`"), "{}", message);
+    }
+
+    #[test]
+    fn runtime_keeps_working_after_a_parse_error() {
+        let runtime = Runtime::new_bare();
+        assert!(runtime.clone().run("x = ".to_string()).is_err());
+        assert_eq!(runtime.run("x = 4; x".to_string()).unwrap().to_string(), "4");
+    }
+}
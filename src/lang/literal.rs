@@ -2,7 +2,7 @@ use super::*;
 use crate::ast::Node;
 use crate::context::Context;
 use crate::interpreter::Rule;
-use crate::result::Result;
+use crate::result::{ErrorKind, Range, Result};
 use crate::runtime::Runtime;
 use std::collections::HashMap;
 
@@ -11,30 +11,58 @@ pub fn get_home() -> Result{
         return Result::String(path.as_path().to_str().unwrap_or("").to_owned());
     }
     else{
-        return Result::Error("Could not get home directory".to_string());
+        return Result::error(ErrorKind::IOError, "Could not get home directory".to_string());
     }
 }
 
-pub fn eval_range(inner: &Vec<Node>, runtime: Arc<Runtime>, ctx: Arc<Context>) -> Result {
-    let mut iter = inner.iter();
-    if let Result::Int(i1) = eval(iter.next().unwrap(), runtime.clone(), ctx.clone()) {
-        if let Result::Int(i2) = eval(iter.next().unwrap(), runtime, ctx) {
-            return Result::Range{
-                start: i1 as usize,
-                end: i2 as usize
-            };
+/// `a..b`, `a..=b` and `a..b..step`, built from the operands of the .. and ..= operators
+pub fn make_range(start: Result, end: Result, step: Result, inclusive: bool) -> Result {
+    for bound in [&start, &end, &step] {
+        if let Result::Error(e) = bound {
+            return Result::Error(e.clone());
         }
     }
-    return Result::Error("Range may only include ints".to_string());
+    if let (Result::Int(start), Result::Int(end), Result::Int(step)) = (start, end, step) {
+        if step == 0 {
+            return Result::error(ErrorKind::ValueError, "Range step can't be 0".to_string());
+        }
+        return Result::Range(Range {
+            start: start,
+            end: end,
+            step: step,
+            inclusive: inclusive,
+        });
+    }
+    return Result::error(ErrorKind::TypeError, "Range may only include ints, linspace creates floats".to_string());
+}
+
+/// Slices with an open end, the missing bound covers the rest of the array or string
+pub fn eval_slice(start: Option<&Node>, end: Option<&Node>, inclusive: bool, runtime: Arc<Runtime>, ctx: Arc<Context>) -> Result {
+    let start = match start {
+        Some(node) => eval(node, runtime.clone(), ctx.clone()),
+        None => Result::Int(0),
+    };
+    let end = match end {
+        Some(node) => eval(node, runtime, ctx),
+        None => Result::Int(i64::MAX),
+    };
+    if let Result::Range(_) = end {
+        return Result::error(ErrorKind::TypeError, "Slices with an open start can't have a step".to_string());
+    }
+    return make_range(start, end, Result::Int(1), inclusive);
 }
 
 
 pub fn eval_array(inner: &Vec<Node>, runtime: Arc<Runtime>, ctx: Arc<Context>) -> Result {
     let mut vec: Vec<Result> = Vec::new();
     for rule in inner {
-        vec.push(eval(&rule, runtime.clone(), ctx.clone()));
+        let value = eval(&rule, runtime.clone(), ctx.clone());
+        if value.is_control_flow() {
+            return value;
+        }
+        vec.push(value);
     }
-    return Result::Array(vec);
+    return Result::array(vec);
 }
 
 pub fn eval_dict(inner: &Vec<Node>, runtime: Arc<Runtime>, ctx: Arc<Context>) -> Result {
@@ -44,12 +72,17 @@ pub fn eval_dict(inner: &Vec<Node>, runtime: Arc<Runtime>, ctx: Arc<Context>) ->
         let txt = eval(&first, runtime.clone(), ctx.clone());
         if let Result::String(txt) = txt {
             let val = eval(&pair.inner().get(1).unwrap(), runtime.clone(), ctx.clone());
+            if val.is_control_flow() {
+                return val;
+            }
             map.insert(txt, val);
         } else if let Result::Error(e) = txt {
             return Result::Error(e);
+        } else if txt.is_control_flow() {
+            return txt;
         }
     }
-    return Result::Dict(map);
+    return Result::dict(map);
 }
 
 pub fn eval_string(inner: &Vec<Node>, runtime: Arc<Runtime>, ctx: Arc<Context>) -> Result {
@@ -65,7 +98,8 @@ pub fn eval_string(inner: &Vec<Node>, runtime: Arc<Runtime>, ctx: Arc<Context>)
                 text += node.content();
             }
             Rule::Interpolation => {
-                text += eval(node.inner().first().unwrap(), runtime.clone(), ctx.clone())
+                let value = eval(node.inner().first().unwrap(), runtime.clone(), ctx.clone());
+                text += display(value, runtime.clone(), ctx.clone())
                     .to_string()
                     .as_str();
             }
@@ -83,14 +117,17 @@ pub fn eval_string(inner: &Vec<Node>, runtime: Arc<Runtime>, ctx: Arc<Context>)
                     text += "\x7f";
                 }
                 any => {
-                    if any.starts_with("x") {
-                        if any.len() > 1 {
-                            text +=
-                                &std::char::from_u32(u32::from_str_radix(&any[1..], 16).unwrap())
-                                    .unwrap()
-                                    .to_string();
-                            continue;
+                    if any.starts_with("x") && any.len() > 1 {
+                        if let Ok(code) = u32::from_str_radix(&any[1..], 16) {
+                            if let Some(c) = std::char::from_u32(code) {
+                                text.push(c);
+                                continue;
+                            }
                         }
+                        return Result::error(
+                            ErrorKind::ValueError,
+                            "Invalid escape sequence \\".to_string() + any,
+                        );
                     }
                     text += any;
                 }
@@ -99,4 +136,16 @@ pub fn eval_string(inner: &Vec<Node>, runtime: Arc<Runtime>, ctx: Arc<Context>)
         }
     }
     return Result::String(text);
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::result::ErrorKind;
+    use crate::runtime::test::*;
+
+    #[test]
+    fn escapes() {
+        assert_eq!(text("\"a\\tb\\x41\""), "a\tbA");
+        assert_eq!(error_kind("\"\\xD800\""), Some(ErrorKind::ValueError));
+    }
+}
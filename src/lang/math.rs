@@ -1,69 +1,118 @@
-use crate::result::Result;
+use crate::result::{ErrorKind, Result};
 use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Error of int arithmetic whose result does not fit into an i64
+fn overflow(operation: &str) -> Result{
+    return Result::error(ErrorKind::ArithmeticError, operation.to_string() + " overflows the range of ints");
+}
 
 pub fn negate(input: Result) -> Result{
     if let Result::Bool(b) = input {
         return Result::Bool(!b);
     }else if let Result::Int(i) = input {
-        return Result::Int(i*-1);
+        return i.checked_neg().map(Result::Int).unwrap_or_else(|| overflow("Negation"));
     }else if let Result::Float(f) = input {
         return Result::Float(f*-1.0);
     }  else if let Result::Error(e) = input {
         return Result::Error(e);
     } else {
-        return Result::Error("Only Bools, ints and floats may be negated".to_string());
+        return Result::error(ErrorKind::TypeError, "Only Bools, ints and floats may be negated".to_string());
+    }
+}
+
+pub fn minus(input: Result) -> Result{
+    if let Result::Int(i) = input {
+        return i.checked_neg().map(Result::Int).unwrap_or_else(|| overflow("Negation"));
+    } else if let Result::Float(f) = input {
+        return Result::Float(-f);
+    } else if let Result::Error(e) = input {
+        return Result::Error(e);
+    } else {
+        return Result::error(ErrorKind::TypeError, "Only ints and floats may be negative".to_string());
     }
 }
 
 
 pub fn compare(lhs: &Result, rhs: &Result) -> Result{
+    return compare_by(lhs, rhs, &|_, _| None);
+}
+
+/// Compares both values, pairs of values the hook orders, like objects with __eq__ and __lt__, are compared by it
+pub fn compare_by(lhs: &Result, rhs: &Result, hook: &dyn Fn(&Result, &Result) -> Option<Result>) -> Result{
+    return compare_with(lhs, rhs, hook, &mut Vec::new());
+}
+
+/// Compares both values, a pair of containers which is already being compared counts as equal so cycles end
+fn compare_with(lhs: &Result, rhs: &Result, hook: &dyn Fn(&Result, &Result) -> Option<Result>, visited: &mut Vec<(*const (), *const ())>) -> Result{
+    if let Some(res) = hook(lhs, rhs) {
+        return res;
+    }
     if let Result::Int(i1) = lhs {
         if let Result::Int(i2) = rhs {
             return Result::Int(compare_int(i1,i2));
         } else if let Result::Error(e) = rhs {
-            return Result::Error(e.to_string());
+            return Result::Error(e.clone());
         } else {
-            return Result::Error("Can only compare ints with each other".to_string());
+            return Result::error(ErrorKind::TypeError, "Can only compare ints with each other".to_string());
         }
     } else if let Result::Float(f1) = lhs {
         if let Result::Float(f2) = rhs {
             return Result::Int(compare_float(f1,f2));
         } else if let Result::Error(e) = rhs {
-            return Result::Error(e.to_string());
+            return Result::Error(e.clone());
         } else {
-            return Result::Error("Can only compare floats with each other".to_string());
+            return Result::error(ErrorKind::TypeError, "Can only compare floats with each other".to_string());
         }
     } else if let Result::String(s1) = lhs {
         if let Result::String(s2) = rhs {
             return Result::Int(compare_string(s1,s2));
         } else if let Result::Error(e) = rhs {
-            return Result::Error(e.to_string());
+            return Result::Error(e.clone());
         } else {
-            return Result::Error("Can only compare strings with each other".to_string());
+            return Result::error(ErrorKind::TypeError, "Can only compare strings with each other".to_string());
         }
     } else if let Result::Bool(b1) = lhs {
         if let Result::Bool(b2) = rhs {
             return Result::Int(compare_bool(b1,b2));
         } else if let Result::Error(e) = rhs {
-            return Result::Error(e.to_string());
+            return Result::Error(e.clone());
         } else {
-            return Result::Error("Can only compare bools with each other".to_string());
+            return Result::error(ErrorKind::TypeError, "Can only compare bools with each other".to_string());
         }
     } else if let Result::Array(a1) = lhs {
         if let Result::Array(a2) = rhs {
-            return compare_array(a1,a2);
+            let pair = (Arc::as_ptr(a1) as *const (), Arc::as_ptr(a2) as *const ());
+            if Arc::ptr_eq(a1, a2) || visited.contains(&pair) {
+                return Result::Int(0);
+            }
+            let a1 = a1.lock().unwrap().clone();
+            let a2 = a2.lock().unwrap().clone();
+            visited.push(pair);
+            let res = compare_array(&a1, &a2, hook, visited);
+            visited.pop();
+            return res;
         } else if let Result::Error(e) = rhs {
-            return Result::Error(e.to_string());
+            return Result::Error(e.clone());
         } else {
-            return Result::Error("Can only compare arrays with each other".to_string());
+            return Result::error(ErrorKind::TypeError, "Can only compare arrays with each other".to_string());
         }
     } else if let Result::Dict(a1) = lhs {
         if let Result::Dict(a2) = rhs {
-            return compare_dict(a1,a2);
+            let pair = (Arc::as_ptr(a1) as *const (), Arc::as_ptr(a2) as *const ());
+            if Arc::ptr_eq(a1, a2) || visited.contains(&pair) {
+                return Result::Int(0);
+            }
+            let m1 = a1.lock().unwrap().clone();
+            let m2 = a2.lock().unwrap().clone();
+            visited.push(pair);
+            let res = compare_dict(&m1, &m2, hook, visited);
+            visited.pop();
+            return res;
         } else if let Result::Error(e) = rhs {
-            return Result::Error(e.to_string());
+            return Result::Error(e.clone());
         } else {
-            return Result::Error("Can only compare dicts with each other".to_string());
+            return Result::error(ErrorKind::TypeError, "Can only compare dicts with each other".to_string());
         }
     } else {
         return lhs.clone();
@@ -122,10 +171,10 @@ pub fn compare_string(value1: &String, value2: &String) -> i64{
 }
 
 
-pub fn compare_array(a1: &Vec<Result>, a2: &Vec<Result>) -> Result{
+fn compare_array(a1: &Vec<Result>, a2: &Vec<Result>, hook: &dyn Fn(&Result, &Result) -> Option<Result>, visited: &mut Vec<(*const (), *const ())>) -> Result{
     if a1.len() == a2.len(){
         for i in 0..a1.len(){
-            let res = compare(&a1[i], &a2[i]);
+            let res = compare_with(&a1[i], &a2[i], hook, visited);
             if let Result::Int(int) = res{
                 if int == -1{
                     return Result::Int(-1);
@@ -138,7 +187,7 @@ pub fn compare_array(a1: &Vec<Result>, a2: &Vec<Result>) -> Result{
                 return Result::Error(e);
             }
             else{
-                return Result::Error("Could not compare both arrays".to_string());
+                return Result::error(ErrorKind::TypeError, "Could not compare both arrays".to_string());
             }
         }
         return Result::Int(0);
@@ -151,10 +200,13 @@ pub fn compare_array(a1: &Vec<Result>, a2: &Vec<Result>) -> Result{
     }
 }
 
-pub fn compare_dict(m1: &HashMap<String,Result>, m2: &HashMap<String,Result>) -> Result{
+fn compare_dict(m1: &HashMap<String,Result>, m2: &HashMap<String,Result>, hook: &dyn Fn(&Result, &Result) -> Option<Result>, visited: &mut Vec<(*const (), *const ())>) -> Result{
     if m1.len() == m2.len(){
         for (key,value) in m1{
-            let res = compare(value, &m2[key]);
+            let res = match m2.get(key){
+                Some(other) => compare_with(value, other, hook, visited),
+                None => Result::Int(1)
+            };
             if let Result::Int(int) = res{
                 if int == -1{
                     return Result::Int(-1);
@@ -167,7 +219,7 @@ pub fn compare_dict(m1: &HashMap<String,Result>, m2: &HashMap<String,Result>) ->
                 return Result::Error(e);
             }
             else{
-                return Result::Error("Could not compare both dicts".to_string());
+                return Result::error(ErrorKind::TypeError, "Could not compare both dicts".to_string());
             }
         }
         return Result::Int(0);
@@ -180,6 +232,34 @@ pub fn compare_dict(m1: &HashMap<String,Result>, m2: &HashMap<String,Result>) ->
     }
 }
 
+/// Result of a comparison operator for the order compare returned, values which can't be compared are only unequal
+pub fn compare_result(op: &str, order: Result) -> Result{
+    let order = if let Result::Int(order) = order { Some(order) } else { None };
+    match op {
+        "==" => {
+            return Result::Bool(order == Some(0));
+        }
+        "!=" => {
+            return Result::Bool(order != Some(0));
+        }
+        "<" => {
+            return Result::Bool(order == Some(-1));
+        }
+        ">" => {
+            return Result::Bool(order == Some(1));
+        }
+        "<=" => {
+            return Result::Bool(matches!(order, Some(-1) | Some(0)));
+        }
+        ">=" => {
+            return Result::Bool(matches!(order, Some(1) | Some(0)));
+        }
+        _ => {
+            return Result::error(ErrorKind::RuntimeError, "Unknown comparison ".to_string() + op);
+        }
+    }
+}
+
 pub fn equals(v1: &Result, v2: &Result) -> Result{
     if let Result::Int(0) = compare(v1, v2){
         return Result::Bool(true);
@@ -189,17 +269,54 @@ pub fn equals(v1: &Result, v2: &Result) -> Result{
     }
 }
 
-pub fn smaller(v1: &Result, v2: &Result) -> Result{
-    if let Result::Int(-1) = compare(v1, v2){
-        return Result::Bool(true);
+/// `value in collection`, checks the values of arrays, the keys of dicts, the substrings of strings and the values of ranges.
+/// Values of arrays are compared with compare_by and the hook
+pub fn contains_by(value: &Result, collection: &Result, hook: &dyn Fn(&Result, &Result) -> Option<Result>) -> Result{
+    if let Result::Error(e) = value {
+        return Result::Error(e.clone());
     }
-    else{
-        return Result::Bool(false);
+    match collection {
+        Result::Array(arr) => {
+            // Comparing may lock the same array, so the values are copied first
+            let arr = arr.lock().unwrap().clone();
+            return Result::Bool(arr.iter().any(|item| matches!(compare_by(item, value, hook), Result::Int(0))));
+        }
+        Result::Dict(map) => {
+            if let Result::String(key) = value {
+                return Result::Bool(map.lock().unwrap().contains_key(key));
+            }
+            return Result::Bool(false);
+        }
+        Result::String(text) => {
+            if let Result::String(part) = value {
+                return Result::Bool(text.contains(part.as_str()));
+            }
+            return Result::error(ErrorKind::TypeError, "Only strings can be searched in strings, not ".to_string() + &value.typename());
+        }
+        Result::Range(range) => {
+            match value {
+                Result::Int(int) => {
+                    return Result::Bool(range.contains(*int));
+                }
+                Result::Float(float) if float.fract() == 0.0 => {
+                    return Result::Bool(range.contains(*float as i64));
+                }
+                _ => {
+                    return Result::Bool(false);
+                }
+            }
+        }
+        Result::Error(e) => {
+            return Result::Error(e.clone());
+        }
+        _ => {
+            return Result::error(ErrorKind::TypeError, "in may only be used with arrays, dicts, strings and ranges, not ".to_string() + &collection.typename());
+        }
     }
 }
 
-pub fn greater(v1: &Result, v2: &Result) -> Result{
-    if let Result::Int(1) = compare(v1, v2){
+pub fn smaller(v1: &Result, v2: &Result) -> Result{
+    if let Result::Int(-1) = compare(v1, v2){
         return Result::Bool(true);
     }
     else{
@@ -229,14 +346,15 @@ pub fn greatereq(v1: &Result, v2: &Result) -> Result{
 pub fn add(mut lhs: Result, rhs: Result) -> Result{
     if let Result::Int(i1) = lhs {
         if let Result::Int(i2) = rhs {
-            return Result::Int(i1 + i2);
+            return i1.checked_add(i2).map(Result::Int).unwrap_or_else(|| overflow("Addition"));
         } else if let Result::Float(f2) = rhs {
             return Result::Float(i1 as f64 + f2);
         } else if let Result::String(s2) = rhs {
             return Result::String(i1.to_string() + s2.as_str());
-        } else if let Result::Array(mut a2) = rhs {
+        } else if let Result::Array(a2) = rhs {
+            let mut a2 = a2.lock().unwrap().clone();
             a2.insert(0, Result::Int(i1));
-            return Result::Array(a2);
+            return Result::array(a2);
         }
     } else if let Result::Float(f1) = lhs {
         if let Result::Int(i2) = rhs {
@@ -245,9 +363,10 @@ pub fn add(mut lhs: Result, rhs: Result) -> Result{
             return Result::Float(f1 + f2);
         } else if let Result::String(s2) = rhs {
             return Result::String(f1.to_string() + s2.as_str());
-        } else if let Result::Array(mut a2) = rhs {
+        } else if let Result::Array(a2) = rhs {
+            let mut a2 = a2.lock().unwrap().clone();
             a2.insert(0, Result::Float(f1));
-            return Result::Array(a2);
+            return Result::array(a2);
         }
     } else if let Result::String(ref s1) = lhs {
         if let Result::String(s2) = rhs {
@@ -256,49 +375,55 @@ pub fn add(mut lhs: Result, rhs: Result) -> Result{
             return Result::String(s1.to_string() + i2.to_string().as_str());
         } else if let Result::Float(f2) = rhs {
             return Result::String(s1.to_string() + f2.to_string().as_str());
-        } else if let Result::Array(mut a2) = rhs {
+        } else if let Result::Array(a2) = rhs {
+            let mut a2 = a2.lock().unwrap().clone();
             a2.insert(0, Result::String(s1.to_string()));
-            return Result::Array(a2);
+            return Result::array(a2);
         }
     } else if let Result::Bool(b1) = lhs {
-        if let Result::Array(mut a2) = rhs {
+        if let Result::Array(a2) = rhs {
+            let mut a2 = a2.lock().unwrap().clone();
             a2.insert(0, Result::Bool(b1));
-            return Result::Array(a2);
+            return Result::array(a2);
         }
-    }else if let Result::Array(mut a1) = lhs {
+    }else if let Result::Array(shared) = lhs {
+        let mut a1 = shared.lock().unwrap().clone();
         if let Result::String(s2) = rhs {
             a1.push(Result::String(s2));
-            return Result::Array(a1);
+            return Result::array(a1);
         } else if let Result::Int(i2) = rhs {
             a1.push(Result::Int(i2));
-            return Result::Array(a1);
+            return Result::array(a1);
         } else if let Result::Float(f2) = rhs {
             a1.push(Result::Float(f2));
-            return Result::Array(a1);
+            return Result::array(a1);
         } else if let Result::Bool(b2) = rhs {
             a1.push(Result::Bool(b2));
-            return Result::Array(a1);
-        } else if let Result::Array(mut a2) = rhs {
+            return Result::array(a1);
+        } else if let Result::Array(a2) = rhs {
+            let mut a2 = a2.lock().unwrap().clone();
             a1.append(&mut a2);
-            return Result::Array(a1);
+            return Result::array(a1);
         }
-        lhs = Result::Array(a1);
+        lhs = Result::Array(shared);
     }
-    else if let Result::Dict(mut m1) = lhs{
+    else if let Result::Dict(shared) = lhs{
         if let Result::Dict(m2) = rhs{
+            let mut m1 = shared.lock().unwrap().clone();
+            let m2 = m2.lock().unwrap().clone();
             for (key,value) in m2{
                 m1.insert(key, value);
             }
-            return Result::Dict(m1);
+            return Result::dict(m1);
         }
-        lhs = Result::Dict(m1);
+        lhs = Result::Dict(shared);
     }
     if let Result::Error(e) = lhs {
         return Result::Error(e);
     } else if let Result::Error(e) = rhs {
         return Result::Error(e);
     } else {
-        return Result::Error("Could not add ".to_string() + lhs.typename().as_str() + " and " + rhs.to_string().as_str());
+        return Result::error(ErrorKind::TypeError, "Could not add ".to_string() + lhs.typename().as_str() + " and " + rhs.to_string().as_str());
     }
 }
 
@@ -306,14 +431,16 @@ pub fn divide(lhs: Result, rhs: Result) -> Result{
     if let Result::Int(i1) = lhs {
         if let Result::Int(i2) = rhs {
             if i2 == 0 {
-                return Result::Error(
+                return Result::error(
+                    ErrorKind::ArithmeticError,
                     "Please don't divide by 0. A kitten just died :(".to_string(),
                 );
             }
             return Result::Float(i1 as f64 / i2 as f64);
         } else if let Result::Float(f2) = rhs {
             if f2 == 0.0 {
-                return Result::Error(
+                return Result::error(
+                    ErrorKind::ArithmeticError,
                     "Please don't divide by 0. A kitten just died :(".to_string(),
                 );
             }
@@ -322,14 +449,16 @@ pub fn divide(lhs: Result, rhs: Result) -> Result{
     } else if let Result::Float(f1) = lhs {
         if let Result::Int(i2) = rhs {
             if i2 == 0 {
-                return Result::Error(
+                return Result::error(
+                    ErrorKind::ArithmeticError,
                     "Please don't divide by 0. A kitten just died :(".to_string(),
                 );
             }
             return Result::Float(f1 / i2 as f64);
         } else if let Result::Float(f2) = rhs {
             if f2 == 0.0 {
-                return Result::Error(
+                return Result::error(
+                    ErrorKind::ArithmeticError,
                     "Please don't divide by 0. A kitten just died :(".to_string(),
                 );
             }
@@ -341,14 +470,14 @@ pub fn divide(lhs: Result, rhs: Result) -> Result{
     } else if let Result::Error(e) = rhs {
         return Result::Error(e);
     } else {
-        return Result::Error("May only divide numbers".to_string());
+        return Result::error(ErrorKind::TypeError, "May only divide numbers".to_string());
     }
 }
 
 pub fn subtract(lhs: Result, rhs: Result) -> Result{
     if let Result::Int(i1) = lhs {
         if let Result::Int(i2) = rhs {
-            return Result::Int(i1 - i2);
+            return i1.checked_sub(i2).map(Result::Int).unwrap_or_else(|| overflow("Subtraction"));
         } else if let Result::Float(f2) = rhs {
             return Result::Float(i1 as f64 - f2);
         }
@@ -364,14 +493,14 @@ pub fn subtract(lhs: Result, rhs: Result) -> Result{
     } else if let Result::Error(e) = rhs {
         return Result::Error(e);
     } else {
-        return Result::Error("May only subtract numbers".to_string());
+        return Result::error(ErrorKind::TypeError, "May only subtract numbers".to_string());
     }
 }
 
 pub fn multiply(lhs: Result, rhs: Result) -> Result{
     if let Result::Int(i1) = lhs {
         if let Result::Int(i2) = rhs {
-            return Result::Int(i1 * i2);
+            return i1.checked_mul(i2).map(Result::Int).unwrap_or_else(|| overflow("Multiplication"));
         } else if let Result::Float(f2) = rhs {
             return Result::Float(i1 as f64 * f2);
         }
@@ -383,23 +512,33 @@ pub fn multiply(lhs: Result, rhs: Result) -> Result{
         }
     } else if let Result::String(s1) = lhs {
         if let Result::Int(i2) = rhs {
-            return Result::String(s1.repeat(i2 as usize));
+            if s1.len().checked_mul(i2.max(0) as usize).is_none_or(|len| len > isize::MAX as usize) {
+                return overflow("Repeating the string");
+            }
+            return Result::String(s1.repeat(i2.max(0) as usize));
         } else if let Result::Error(e) = rhs {
             return Result::Error(e);
         } else {
-            return Result::Error("String may only be multiplied with an int".to_string());
+            return Result::error(ErrorKind::TypeError, "String may only be multiplied with an int".to_string());
         }
-    } else if let Result::Array(mut a1) = lhs {
+    } else if let Result::Array(a1) = lhs {
         if let Result::Int(i2) = rhs {
-            let mut newvec = Vec::with_capacity(a1.len() * i2 as usize);
+            let a1 = a1.lock().unwrap().clone();
+            let capacity = match a1.len().checked_mul(i2.max(0) as usize) {
+                Some(capacity) if capacity <= isize::MAX as usize / std::mem::size_of::<Result>() => capacity,
+                _ => {
+                    return overflow("Repeating the array");
+                }
+            };
+            let mut newvec = Vec::with_capacity(capacity);
             for _ in 0..i2{
                 newvec.append(&mut a1.clone());
             }
-            return Result::Array(newvec);
+            return Result::array(newvec);
         } else if let Result::Error(e) = rhs {
             return Result::Error(e);
         } else {
-            return Result::Error("Arrays may only be multiplied with an int".to_string());
+            return Result::error(ErrorKind::TypeError, "Arrays may only be multiplied with an int".to_string());
         }
     }
 
@@ -408,22 +547,25 @@ pub fn multiply(lhs: Result, rhs: Result) -> Result{
     } else if let Result::Error(e) = rhs {
         return Result::Error(e);
     } else {
-        return Result::Error("May only multiply numbers".to_string());
+        return Result::error(ErrorKind::TypeError, "May only multiply numbers".to_string());
     }
 }
 
 pub fn modulo(lhs: Result, rhs: Result) -> Result{
     if let Result::Int(i1) = lhs {
         if let Result::Int(i2) = rhs {
-            return Result::Int(i1 % i2);
+            if i2 == 0 {
+                return Result::error(ErrorKind::ArithmeticError, "Modulo by 0 is not defined".to_string());
+            }
+            return i1.checked_rem(i2).map(Result::Int).unwrap_or_else(|| overflow("Modulo"));
         } else if let Result::Float(_f2) = rhs {
-            return Result::Error("Cannot apply modulo on Int and Float Numbers".to_string());
+            return Result::error(ErrorKind::TypeError, "Cannot apply modulo on Int and Float Numbers".to_string());
         }
     } else if let Result::Float(_f1) = lhs {
         if let Result::Int(_i2) = rhs {
-            return Result::Error("Cannot apply modulo on Int and Float Numbers".to_string());
+            return Result::error(ErrorKind::TypeError, "Cannot apply modulo on Int and Float Numbers".to_string());
         } else if let Result::Float(_f2) = rhs {
-            return Result::Error("Cannot apply modulo on two Float Numbers".to_string());
+            return Result::error(ErrorKind::TypeError, "Cannot apply modulo on two Float Numbers".to_string());
         }
     }
     if let Result::Error(e) = lhs {
@@ -431,7 +573,7 @@ pub fn modulo(lhs: Result, rhs: Result) -> Result{
     } else if let Result::Error(e) = rhs {
         return Result::Error(e);
     } else {
-        return Result::Error("May only use modulo ints".to_string());
+        return Result::error(ErrorKind::TypeError, "May only use modulo ints".to_string());
     }
 }
 
@@ -445,7 +587,7 @@ pub fn power(lhs: Result, rhs: Result) -> Result{
         } else if let Result::Error(e) = rhs {
             return Result::Error(e);
         } else {
-            return Result::Error("May only use power on numbers".to_string());
+            return Result::error(ErrorKind::TypeError, "May only use power on numbers".to_string());
         }
         if i2 < 0.0 {
             return divide(Result::Int(1), Result::Float((i1 as f64).powf(i2 * -1.0)));
@@ -461,7 +603,7 @@ pub fn power(lhs: Result, rhs: Result) -> Result{
         } else if let Result::Error(e) = rhs {
             return Result::Error(e);
         } else {
-            return Result::Error("May only use power on numbers".to_string());
+            return Result::error(ErrorKind::TypeError, "May only use power on numbers".to_string());
         }
         if i2 < 0.0 {
             return divide(Result::Int(1), Result::Float((f1 as f64).powf(i2 * -1.0)));
@@ -472,7 +614,7 @@ pub fn power(lhs: Result, rhs: Result) -> Result{
     if let Result::Error(e) = lhs {
         return Result::Error(e);
     } else {
-        return Result::Error("May only use power on numbers".to_string());
+        return Result::error(ErrorKind::TypeError, "May only use power on numbers".to_string());
     }
 }
 
@@ -486,7 +628,7 @@ pub fn root(lhs: Result, rhs: Result) -> Result{
         } else if let Result::Error(e) = rhs {
             return Result::Error(e);
         } else {
-            return Result::Error("May only use root on numbers".to_string());
+            return Result::error(ErrorKind::TypeError, "May only use root on numbers".to_string());
         }
         if i2 < 0.0 {
             return divide(
@@ -505,7 +647,7 @@ pub fn root(lhs: Result, rhs: Result) -> Result{
         } else if let Result::Error(e) = rhs {
             return Result::Error(e);
         } else {
-            return Result::Error("May only use root on numbers".to_string());
+            return Result::error(ErrorKind::TypeError, "May only use root on numbers".to_string());
         }
         if i2 < 0.0 {
             return divide(
@@ -520,15 +662,20 @@ pub fn root(lhs: Result, rhs: Result) -> Result{
     if let Result::Error(e) = lhs {
         return Result::Error(e);
     } else {
-        return Result::Error("May only use root on numbers".to_string());
+        return Result::error(ErrorKind::TypeError, "May only use root on numbers".to_string());
     }
 }
 
 pub fn faculty(num: Result) -> Result{
     if let Result::Int(int) = num {
         let mut res: i64 = 1;
-        for i in 2..(int + 1) {
-            res *= i;
+        for i in 2..=int {
+            res = match res.checked_mul(i) {
+                Some(res) => res,
+                None => {
+                    return overflow(&("Faculty of ".to_string() + &int.to_string()));
+                }
+            };
         }
         return Result::Int(res);
     }
@@ -536,19 +683,19 @@ pub fn faculty(num: Result) -> Result{
     if let Result::Error(e) = num {
         return Result::Error(e);
     } else {
-        return Result::Error("May only use faculty on numbers".to_string());
+        return Result::error(ErrorKind::TypeError, "May only use faculty on numbers".to_string());
     }
 }
 
 pub fn abs(num: Result) -> Result{
     if let Result::Int(int) = num {
-        return Result::Int(int.abs());
+        return int.checked_abs().map(Result::Int).unwrap_or_else(|| overflow("Absolute value"));
     } else if let Result::Float(float) = num {
         return Result::Float(float.abs());
     } else if let Result::Error(e) = num {
         return Result::Error(e);
     } else {
-        return Result::Error("Function abs may only be used with a float or int".to_string());
+        return Result::error(ErrorKind::TypeError, "Function abs may only be used with a float or int".to_string());
     }
 }
 
@@ -560,7 +707,7 @@ pub fn ceil(num: Result) -> Result{
     } else if let Result::Error(e) = num {
         return Result::Error(e);
     } else {
-        return Result::Error("Function ceil may only be used with a float or int".to_string());
+        return Result::error(ErrorKind::TypeError, "Function ceil may only be used with a float or int".to_string());
     }
 }
 
@@ -572,7 +719,7 @@ pub fn floor(num: Result) -> Result{
     } else if let Result::Error(e) = num {
         return Result::Error(e);
     } else {
-        return Result::Error("Function floor may only be used with a float or int".to_string());
+        return Result::error(ErrorKind::TypeError, "Function floor may only be used with a float or int".to_string());
     }
 }
 
@@ -584,7 +731,7 @@ pub fn sin(num: Result) -> Result{
     } else if let Result::Error(e) = num {
         return Result::Error(e);
     } else {
-        return Result::Error("Function sin may only be used with a float or int".to_string());
+        return Result::error(ErrorKind::TypeError, "Function sin may only be used with a float or int".to_string());
     }
 }
 
@@ -596,7 +743,7 @@ pub fn cos(num: Result) -> Result{
     } else if let Result::Error(e) = num {
         return Result::Error(e);
     } else {
-        return Result::Error("Function cos may only be used with a float or int".to_string());
+        return Result::error(ErrorKind::TypeError, "Function cos may only be used with a float or int".to_string());
     }
 }
 
@@ -608,7 +755,7 @@ pub fn tan(num: Result) -> Result{
     } else if let Result::Error(e) = num {
         return Result::Error(e);
     } else {
-        return Result::Error("Function tan may only be used with a float or int".to_string());
+        return Result::error(ErrorKind::TypeError, "Function tan may only be used with a float or int".to_string());
     }
 }
 
@@ -620,7 +767,7 @@ pub fn asin(num: Result) -> Result{
     } else if let Result::Error(e) = num {
         return Result::Error(e);
     } else {
-        return Result::Error("Function asin may only be used with a float or int".to_string());
+        return Result::error(ErrorKind::TypeError, "Function asin may only be used with a float or int".to_string());
     }
 }
 
@@ -632,7 +779,7 @@ pub fn acos(num: Result) -> Result{
     } else if let Result::Error(e) = num {
         return Result::Error(e);
     } else {
-        return Result::Error("Function acos may only be used with a float or int".to_string());
+        return Result::error(ErrorKind::TypeError, "Function acos may only be used with a float or int".to_string());
     }
 }
 
@@ -644,7 +791,7 @@ pub fn atan(num: Result) -> Result{
     } else if let Result::Error(e) = num {
         return Result::Error(e);
     } else {
-        return Result::Error("Function atan may only be used with a float or int".to_string());
+        return Result::error(ErrorKind::TypeError, "Function atan may only be used with a float or int".to_string());
     }
 }
 
@@ -656,7 +803,7 @@ pub fn sinh(num: Result) -> Result{
     } else if let Result::Error(e) = num {
         return Result::Error(e);
     } else {
-        return Result::Error("Function sinh may only be used with a float or int".to_string());
+        return Result::error(ErrorKind::TypeError, "Function sinh may only be used with a float or int".to_string());
     }
 }
 
@@ -668,7 +815,7 @@ pub fn cosh(num: Result) -> Result{
     } else if let Result::Error(e) = num {
         return Result::Error(e);
     } else {
-        return Result::Error("Function cosh may only be used with a float or int".to_string());
+        return Result::error(ErrorKind::TypeError, "Function cosh may only be used with a float or int".to_string());
     }
 }
 
@@ -680,7 +827,7 @@ pub fn tanh(num: Result) -> Result{
     } else if let Result::Error(e) = num {
         return Result::Error(e);
     } else {
-        return Result::Error("Function tanh may only be used with a float or int".to_string());
+        return Result::error(ErrorKind::TypeError, "Function tanh may only be used with a float or int".to_string());
     }
 }
 
@@ -692,7 +839,7 @@ pub fn asinh(num: Result) -> Result{
     } else if let Result::Error(e) = num {
         return Result::Error(e);
     } else {
-        return Result::Error("Function asinh may only be used with a float or int".to_string());
+        return Result::error(ErrorKind::TypeError, "Function asinh may only be used with a float or int".to_string());
     }
 }
 
@@ -704,7 +851,7 @@ pub fn acosh(num: Result) -> Result{
     } else if let Result::Error(e) = num {
         return Result::Error(e);
     } else {
-        return Result::Error("Function acosh may only be used with a float or int".to_string());
+        return Result::error(ErrorKind::TypeError, "Function acosh may only be used with a float or int".to_string());
     }
 }
 
@@ -716,7 +863,7 @@ pub fn atanh(num: Result) -> Result{
     } else if let Result::Error(e) = num {
         return Result::Error(e);
     } else {
-        return Result::Error("Function atanh may only be used with a float or int".to_string());
+        return Result::error(ErrorKind::TypeError, "Function atanh may only be used with a float or int".to_string());
     }
 }
 
@@ -728,7 +875,7 @@ pub fn signum(num: Result) -> Result{
     } else if let Result::Error(e) = num {
         return Result::Error(e);
     } else {
-        return Result::Error("Function signum may only be used with a float or int".to_string());
+        return Result::error(ErrorKind::TypeError, "Function signum may only be used with a float or int".to_string());
     }
 }
 
@@ -740,7 +887,7 @@ pub fn round(num: Result) -> Result{
     } else if let Result::Error(e) = num {
         return Result::Error(e);
     } else {
-        return Result::Error("Function round may only be used with a float or int".to_string());
+        return Result::error(ErrorKind::TypeError, "Function round may only be used with a float or int".to_string());
     }
 }
 
@@ -753,7 +900,7 @@ pub fn log(num: Result, base: Result) -> Result{
         } else if let Result::Error(e) = base {
             return Result::Error(e);
         } else {
-            return Result::Error("The base for the log needs to be a float or int".to_string());
+            return Result::error(ErrorKind::TypeError, "The base for the log needs to be a float or int".to_string());
         }
     } else if let Result::Float(float) = num {
         if let Result::Int(intbase) = base {
@@ -763,11 +910,94 @@ pub fn log(num: Result, base: Result) -> Result{
         } else if let Result::Error(e) = base {
             return Result::Error(e);
         } else {
-            return Result::Error("The base for the log needs to be a float or int".to_string());
+            return Result::error(ErrorKind::TypeError, "The base for the log needs to be a float or int".to_string());
         }
     } else if let Result::Error(e) = num {
         return Result::Error(e);
     } else {
-        return Result::Error("Function log may only be used with a float or int".to_string());
+        return Result::error(ErrorKind::TypeError, "Function log may only be used with a float or int".to_string());
+    }
+}
+
+fn as_float(num: &Result) -> Option<f64>{
+    match num {
+        Result::Int(int) => {
+            return Some(*int as f64);
+        }
+        Result::Float(float) => {
+            return Some(*float);
+        }
+        _ => {
+            return None;
+        }
     }
-}
\ No newline at end of file
+}
+
+/// n evenly spaced floats from start to end, both ends included
+pub fn linspace(start: Result, end: Result, n: Result) -> Result{
+    for arg in [&start, &end, &n] {
+        if let Result::Error(e) = arg {
+            return Result::Error(e.clone());
+        }
+    }
+    if let (Some(start), Some(end), Result::Int(n)) = (as_float(&start), as_float(&end), n) {
+        if n < 0 {
+            return Result::error(ErrorKind::ValueError, "Linspace can't create a negative number of values".to_string());
+        }
+        if n == 1 {
+            return Result::array(vec![Result::Float(start)]);
+        }
+        let step = (end - start) / (n - 1) as f64;
+        return Result::array((0..n).map(|i| Result::Float(start + i as f64 * step)).collect());
+    }
+    return Result::error(ErrorKind::TypeError, "Linspace needs two numbers and an int".to_string());
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::result::ErrorKind;
+    use crate::runtime::test::*;
+
+    #[test]
+    fn int_overflow() {
+        assert_eq!(error_kind("9223372036854775807 + 1"), Some(ErrorKind::ArithmeticError));
+        assert_eq!(error_kind("m = -9223372036854775807; m - 2"), Some(ErrorKind::ArithmeticError));
+        assert_eq!(error_kind("9223372036854775807 * 2"), Some(ErrorKind::ArithmeticError));
+        assert_eq!(error_kind("abs(-9223372036854775807 - 1)"), Some(ErrorKind::ArithmeticError));
+        assert_eq!(text("9223372036854775806 + 1"), "9223372036854775807");
+    }
+
+    #[test]
+    fn modulo() {
+        assert_eq!(text("7 % -3"), "1");
+        assert_eq!(error_kind("5 % 0"), Some(ErrorKind::ArithmeticError));
+        assert_eq!(error_kind("m = -9223372036854775807 - 1; m % -1"), Some(ErrorKind::ArithmeticError));
+    }
+
+    #[test]
+    fn faculty() {
+        assert_eq!(text("5!"), "120");
+        assert_eq!(error_kind("30!"), Some(ErrorKind::ArithmeticError));
+    }
+
+    #[test]
+    fn repeat() {
+        assert_eq!(text("\"ab\" * 2"), "abab");
+        assert_eq!(error_kind("\"ab\" * 9223372036854775807"), Some(ErrorKind::ArithmeticError));
+        assert_eq!(error_kind("[1] * 9223372036854775807"), Some(ErrorKind::ArithmeticError));
+    }
+
+    #[test]
+    fn membership() {
+        assert_eq!(text("[2 in [1, 2], [2] in [1, [2]], 3 in [1, 2]]"), "[ true, true, false ]");
+        assert_eq!(text("[\"a\" in {\"a\": 1}, 1 in {\"a\": 1}]"), "[ true, false ]");
+        assert_eq!(text("[\"ell\" in \"hello\", \"x\" not in \"hello\"]"), "[ true, true ]");
+        assert_eq!(text("x = -1; [x in -5..0, 3 not in 0..3, 5 in 0..10..5]"), "[ true, true, true ]");
+    }
+
+    #[test]
+    fn membership_in_other_values() {
+        assert_eq!(error_kind("1 in 5"), Some(ErrorKind::TypeError));
+        assert_eq!(error_kind("1 in \"abc\""), Some(ErrorKind::TypeError));
+    }
+}
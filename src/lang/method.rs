@@ -0,0 +1,557 @@
+use super::*;
+use crate::ast::Node;
+use crate::context::Context;
+use crate::result::{ErrorKind, Result};
+use crate::runtime::Runtime;
+use std::collections::HashMap;
+
+/// Key of the dict an object inherits its methods from
+pub const PROTOTYPE: &str = "__proto__";
+
+/// Calls obj.name(args), functions found in a dict or its prototypes get the dict as self, other names are builtin methods of the type.
+/// super.name(args) starts the lookup above the current method and keeps the current self
+pub fn eval_methodcall(receiver: Result, node: &Node, through_super: bool, runtime: Arc<Runtime>, ctx: Arc<Context>) -> Result {
+    let name = &node.inner()[0].content()[1..];
+    let (args, keywords) = match eval_arguments(&mut node.inner()[1..].iter(), runtime.clone(), ctx.clone()) {
+        Ok(arguments) => arguments,
+        Err(res) => {
+            return res;
+        }
+    };
+    let this = if through_super { ctx.var("self") } else { receiver.clone() };
+    if let Some((method, holder)) = find_method(&receiver, name) {
+        if let Result::Function { .. } = method {
+            return apply_function(&method, (args, keywords), Some((this, prototype(&holder))), name, Some(node), runtime, ctx);
+        }
+        return Result::error(ErrorKind::TypeError, "Key ".to_string() + name + " holds a value of type " + &method.typename() + ", not a method");
+    }
+    if through_super {
+        return Result::error(ErrorKind::NameError, "No prototype above the current method has a method ".to_string() + name);
+    }
+    if let (Result::Dict(_), "new") = (&receiver, name) {
+        return construct(receiver, args, keywords, node, runtime, ctx);
+    }
+    return builtin_method(this, name, args, keywords, Some(node), runtime, ctx);
+}
+
+/// Looks the key up in the dict and then in its prototypes, returns the value and the dict it was found in
+pub fn find_method(object: &Result, name: &str) -> Option<(Result, Result)> {
+    let mut current = object.clone();
+    // Prototypes may form a loop, each dict is searched once
+    let mut seen = Vec::new();
+    while let Result::Dict(dict) = &current {
+        if seen.iter().any(|other| Arc::ptr_eq(other, dict)) {
+            return None;
+        }
+        seen.push(dict.clone());
+        let (value, next) = {
+            let map = dict.lock().unwrap();
+            (map.get(name).cloned(), map.get(PROTOTYPE).cloned())
+        };
+        if let Some(value) = value {
+            return Some((value, current));
+        }
+        current = next.unwrap_or(Result::None);
+    }
+    return None;
+}
+
+fn prototype(object: &Result) -> Result {
+    if let Result::Dict(dict) = object {
+        if let Some(parent) = dict.lock().unwrap().get(PROTOTYPE) {
+            return parent.clone();
+        }
+    }
+    return Result::None;
+}
+
+/// A copy of the methods which inherits from the parent class
+pub fn make_class(methods: Result, parent: Result) -> Result {
+    if let Result::Dict(methods) = methods {
+        let mut map = methods.lock().unwrap().clone();
+        match parent {
+            Result::Dict(_) => {
+                map.insert(PROTOTYPE.to_string(), parent);
+            }
+            Result::None => {}
+            Result::Error(e) => {
+                return Result::Error(e);
+            }
+            _ => {
+                return Result::error(ErrorKind::TypeError, "A class may only inherit from a dict, not from ".to_string() + &parent.typename());
+            }
+        }
+        return Result::dict(map);
+    } else if let Result::Error(e) = methods {
+        return Result::Error(e);
+    }
+    return Result::error(ErrorKind::TypeError, "A class is created from a dict of methods, not from ".to_string() + &methods.typename());
+}
+
+/// Creates an object inheriting from the class, the init method of the class is called with the arguments
+fn construct(
+    class: Result,
+    args: Vec<Result>,
+    keywords: Vec<(String, Result)>,
+    callsite: &Node,
+    runtime: Arc<Runtime>,
+    ctx: Arc<Context>,
+) -> Result {
+    let mut fields = HashMap::new();
+    fields.insert(PROTOTYPE.to_string(), class);
+    let object = Result::dict(fields);
+    if let Some((init, holder)) = find_method(&object, "init") {
+        let res = apply_function(&init, (args, keywords), Some((object.clone(), prototype(&holder))), "init", Some(callsite), runtime, ctx);
+        if let Result::Error(e) = res {
+            return Result::Error(e);
+        }
+    } else if !args.is_empty() || !keywords.is_empty() {
+        return Result::error(ErrorKind::ArgumentError, "Objects of classes without init are created without arguments".to_string());
+    }
+    return object;
+}
+
+/// Smallest and largest number of arguments of the builtin methods, none if the type has no such method
+fn method_arity(typename: &str, name: &str) -> Option<(usize, usize)> {
+    let collection = matches!(typename, "array" | "dict" | "string" | "range");
+    let iterable = matches!(typename, "array" | "dict" | "range");
+    match name {
+        "type" | "string" | "copy" | "deepcopy" => Some((0, 0)),
+        "len" | "enumerate" if collection => Some((0, 0)),
+        "contains" if collection => Some((1, 1)),
+        "map" | "each" if iterable => Some((1, 1)),
+        "pmap" | "peach" if typename == "array" || typename == "dict" => Some((1, 2)),
+        "push" | "join" if typename == "array" => Some((1, 1)),
+        "pop" if typename == "array" => Some((0, 0)),
+        "keys" | "values" if typename == "dict" => Some((0, 0)),
+        "trim" | "upper" | "lower" if typename == "string" => Some((0, 0)),
+        "split" | "starts_with" | "ends_with" if typename == "string" => Some((1, 1)),
+        "replace" if typename == "string" => Some((2, 2)),
+        "abs" | "round" | "floor" | "ceil" if typename == "int" || typename == "float" => Some((0, 0)),
+        "recv" | "try_recv" | "close" if typename == "channel" => Some((0, 0)),
+        "send" if typename == "channel" => Some((1, 1)),
+        "kind" | "message" | "payload" if typename == "error" => Some((0, 0)),
+        _ => None,
+    }
+}
+
+/// Methods every value of a type has, most of them call the builtin function of the same name with the value as first argument
+fn builtin_method(
+    this: Result,
+    name: &str,
+    mut args: Vec<Result>,
+    keywords: Vec<(String, Result)>,
+    callsite: Option<&Node>,
+    runtime: Arc<Runtime>,
+    ctx: Arc<Context>,
+) -> Result {
+    let typename = this.typename();
+    let (min, max) = match method_arity(&typename, name) {
+        Some(arity) => arity,
+        None => {
+            // Errors pass through methods, like through the builtin functions
+            if let Result::Error(e) = this {
+                return Result::Error(e);
+            }
+            return Result::error(ErrorKind::NameError, "Type ".to_string() + &typename + " has no method " + name);
+        }
+    };
+    for (key, value) in keywords {
+        if key == "workers" && max == 2 && args.len() == 1 {
+            args.push(value);
+        } else {
+            return Result::error(ErrorKind::ArgumentError, "Method ".to_string() + name + " of " + &typename + " has no parameter " + &key);
+        }
+    }
+    if args.len() < min || args.len() > max {
+        let arguments = if max == 1 { " argument, but " } else { " arguments, but " };
+        let count = if min == max { max.to_string() } else { min.to_string() + " to " + &max.to_string() };
+        return Result::error(ErrorKind::ArgumentError, "Method ".to_string() + name + " of " + &typename + " takes " + &count + arguments + &args.len().to_string() + " were passed");
+    }
+    args.resize(max, Result::None);
+    let mut args = args.into_iter();
+    let mut arg = || args.next().unwrap();
+    match name {
+        "type" => {
+            return Result::String(typename);
+        }
+        "string" => {
+            return display(this, runtime, ctx);
+        }
+        "copy" => {
+            return this.copy();
+        }
+        "deepcopy" => {
+            return this.deepcopy();
+        }
+        "len" => {
+            return len(this);
+        }
+        "enumerate" => {
+            return enumerate(this);
+        }
+        "contains" => {
+            return contains_value(&arg(), &this, runtime, ctx);
+        }
+        "map" => {
+            return map(this, arg(), callsite, runtime, ctx);
+        }
+        "each" => {
+            return each(this, arg(), callsite, runtime, ctx);
+        }
+        "pmap" => {
+            return pmap(this, arg(), arg(), callsite, runtime, ctx);
+        }
+        "peach" => {
+            return peach(this, arg(), arg(), callsite, runtime, ctx);
+        }
+        "push" => {
+            return push(this, arg());
+        }
+        "pop" => {
+            return pop(this);
+        }
+        "join" => {
+            return join(this, arg(), runtime, ctx);
+        }
+        "keys" | "values" => {
+            if let Result::Dict(map) = this {
+                let map = map.lock().unwrap();
+                if name == "keys" {
+                    return Result::array(map.keys().map(|key| Result::String(key.clone())).collect());
+                }
+                return Result::array(map.values().cloned().collect());
+            }
+        }
+        "trim" => {
+            return map_string(this, "Trim", |text| text.trim().to_string());
+        }
+        "upper" => {
+            return map_string(this, "Upper", |text| text.to_uppercase());
+        }
+        "lower" => {
+            return map_string(this, "Lower", |text| text.to_lowercase());
+        }
+        "split" => {
+            return split(this, arg());
+        }
+        "starts_with" => {
+            return starts_with(this, arg());
+        }
+        "ends_with" => {
+            return ends_with(this, arg());
+        }
+        "replace" => {
+            return replace(this, arg(), arg());
+        }
+        "abs" => {
+            return abs(this);
+        }
+        "round" => {
+            return round(this);
+        }
+        "floor" => {
+            return floor(this);
+        }
+        "ceil" => {
+            return ceil(this);
+        }
+        "send" => {
+            return channel_send(this, arg());
+        }
+        "recv" => {
+            return channel_recv(this);
+        }
+        "try_recv" => {
+            return channel_try_recv(this);
+        }
+        "close" => {
+            return channel_close(this);
+        }
+        "kind" | "message" | "payload" => {
+            if let Result::Error(e) = &this {
+                if let Result::Dict(fields) = error_dict(e) {
+                    return fields.lock().unwrap().get(name).cloned().unwrap_or(Result::None);
+                }
+            }
+        }
+        _ => {}
+    }
+    return Result::error(ErrorKind::RuntimeError, "Method ".to_string() + name + " of " + &typename + " is not implemented");
+}
+
+/// Calls the special method of an object with the object as self, none if the value has no such method
+pub fn call_special(
+    object: &Result,
+    key: &str,
+    arguments: (Vec<Result>, Vec<(String, Result)>),
+    callsite: Option<&Node>,
+    runtime: Arc<Runtime>,
+    ctx: Arc<Context>,
+) -> Option<Result> {
+    let (method, holder) = find_method(object, key)?;
+    if let Result::Function { .. } = method {
+        return Some(apply_function(&method, arguments, Some((object.clone(), prototype(&holder))), key, callsite, runtime, ctx));
+    }
+    return Some(Result::error(ErrorKind::TypeError, "Key ".to_string() + key + " holds a value of type " + &method.typename() + ", not a method"));
+}
+
+/// Special method of the left operand which overloads an arithmetic operator
+fn operator_method(op: &str) -> Option<&'static str> {
+    match op {
+        "+" => Some("__add__"),
+        "-" => Some("__sub__"),
+        "*" => Some("__mul__"),
+        "/" => Some("__div__"),
+        "%" => Some("__mod__"),
+        "**" | "^" => Some("__pow__"),
+        _ => None,
+    }
+}
+
+/// Applies an operator overloaded by an object, none if neither operand overloads it.
+/// Arithmetic calls the method of the left operand, == and != use __eq__ and the other comparisons are derived from __lt__ and __eq__
+pub fn overload(op: &str, lhs: &Result, rhs: &Result, runtime: Arc<Runtime>, ctx: Arc<Context>) -> Option<Result> {
+    match op {
+        "==" => {
+            return object_equals(lhs, rhs, runtime, ctx);
+        }
+        "!=" => {
+            return object_equals(lhs, rhs, runtime, ctx).map(negate);
+        }
+        "<" => {
+            return object_less(lhs, rhs, runtime, ctx);
+        }
+        ">" => {
+            return object_less(rhs, lhs, runtime, ctx);
+        }
+        "<=" => {
+            return object_less(rhs, lhs, runtime, ctx).map(negate);
+        }
+        ">=" => {
+            return object_less(lhs, rhs, runtime, ctx).map(negate);
+        }
+        _ => {
+            let key = operator_method(op)?;
+            return call_special(lhs, key, (vec![rhs.clone()], Vec::new()), None, runtime, ctx);
+        }
+    }
+}
+
+/// Comparison methods have to return bools, so their results can be negated and chained
+fn expect_bool(key: &str, res: Result) -> Result {
+    match res {
+        Result::Bool(_) | Result::Error(_) => {
+            return res;
+        }
+        _ => {
+            return Result::error(ErrorKind::TypeError, key.to_string() + " needs to return a bool, not " + &res.typename());
+        }
+    }
+}
+
+fn object_equals(lhs: &Result, rhs: &Result, runtime: Arc<Runtime>, ctx: Arc<Context>) -> Option<Result> {
+    if let Some(res) = call_special(lhs, "__eq__", (vec![rhs.clone()], Vec::new()), None, runtime.clone(), ctx.clone()) {
+        return Some(expect_bool("__eq__", res));
+    }
+    let res = call_special(rhs, "__eq__", (vec![lhs.clone()], Vec::new()), None, runtime, ctx)?;
+    return Some(expect_bool("__eq__", res));
+}
+
+/// lhs < rhs, if only the right operand has __lt__ it is rhs > lhs, so neither rhs < lhs nor rhs == lhs
+fn object_less(lhs: &Result, rhs: &Result, runtime: Arc<Runtime>, ctx: Arc<Context>) -> Option<Result> {
+    if let Some(res) = call_special(lhs, "__lt__", (vec![rhs.clone()], Vec::new()), None, runtime.clone(), ctx.clone()) {
+        return Some(expect_bool("__lt__", res));
+    }
+    let greater = expect_bool("__lt__", call_special(rhs, "__lt__", (vec![lhs.clone()], Vec::new()), None, runtime.clone(), ctx.clone())?);
+    if let Result::Bool(false) = greater {
+        return match object_equals(rhs, lhs, runtime, ctx) {
+            Some(equal) => Some(negate(equal)),
+            None => Some(Result::error(ErrorKind::TypeError, "Only the right operand has __lt__, so it also needs __eq__ to order both".to_string())),
+        };
+    }
+    return Some(negate(greater));
+}
+
+/// Order of two values for compare_by, none unless one of them has __eq__ or __lt__
+fn object_order(lhs: &Result, rhs: &Result, runtime: Arc<Runtime>, ctx: Arc<Context>) -> Option<Result> {
+    let equal = object_equals(lhs, rhs, runtime.clone(), ctx.clone());
+    match &equal {
+        Some(Result::Bool(true)) => {
+            return Some(Result::Int(0));
+        }
+        Some(Result::Error(e)) => {
+            return Some(Result::Error(e.clone()));
+        }
+        _ => {}
+    }
+    let less = match object_less(lhs, rhs, runtime.clone(), ctx.clone()) {
+        Some(less) => less,
+        None => {
+            return equal.map(|_| Result::error(ErrorKind::TypeError, "Objects without __lt__ can only be compared for equality".to_string()));
+        }
+    };
+    match less {
+        Result::Bool(true) => {
+            return Some(Result::Int(-1));
+        }
+        Result::Bool(false) if equal.is_some() => {
+            return Some(Result::Int(1));
+        }
+        Result::Bool(false) => {
+            let greater = object_less(rhs, lhs, runtime, ctx)?;
+            return Some(match greater {
+                Result::Bool(true) => Result::Int(1),
+                Result::Bool(false) => Result::Int(0),
+                _ => greater,
+            });
+        }
+        _ => {
+            return Some(less);
+        }
+    }
+}
+
+/// compare which uses __eq__ and __lt__ of objects, also inside of arrays and dicts
+pub fn compare_values(lhs: &Result, rhs: &Result, runtime: Arc<Runtime>, ctx: Arc<Context>) -> Result {
+    return compare_by(lhs, rhs, &|lhs, rhs| object_order(lhs, rhs, runtime.clone(), ctx.clone()));
+}
+
+/// `value in collection` which uses __eq__ of objects
+pub fn contains_value(value: &Result, collection: &Result, runtime: Arc<Runtime>, ctx: Arc<Context>) -> Result {
+    return contains_by(value, collection, &|lhs, rhs| object_order(lhs, rhs, runtime.clone(), ctx.clone()));
+}
+
+/// Text of a value, objects with __str__ decide their own text, also inside of arrays and dicts
+pub fn display(value: Result, runtime: Arc<Runtime>, ctx: Arc<Context>) -> Result {
+    return display_with(value, &mut Vec::new(), runtime, ctx);
+}
+
+/// Containers which are already being displayed are shown as `[...]` or `{...}`, like in to_string
+fn display_with(value: Result, visited: &mut Vec<*const ()>, runtime: Arc<Runtime>, ctx: Arc<Context>) -> Result {
+    if let Some(res) = call_special(&value, "__str__", (Vec::new(), Vec::new()), None, runtime.clone(), ctx.clone()) {
+        if let Result::String(_) | Result::Error(_) = res {
+            return res;
+        }
+        return Result::error(ErrorKind::TypeError, "__str__ needs to return a string, not ".to_string() + &res.typename());
+    }
+    let (ptr, open, close, items) = match &value {
+        Result::Array(arr) => {
+            let items = arr.lock().unwrap().iter().map(|item| (None, item.clone())).collect::<Vec<_>>();
+            (Arc::as_ptr(arr) as *const (), "[", "]", items)
+        }
+        Result::Dict(map) => {
+            let items = map.lock().unwrap().iter().map(|(key, item)| (Some(key.clone()), item.clone())).collect::<Vec<_>>();
+            (Arc::as_ptr(map) as *const (), "{", "}", items)
+        }
+        _ => {
+            return cast_string(value);
+        }
+    };
+    if visited.contains(&ptr) {
+        return Result::String(open.to_string() + "..." + close);
+    }
+    visited.push(ptr);
+    let mut txt = String::new();
+    for (key, item) in items {
+        txt += ", ";
+        if let Some(key) = key {
+            txt = txt + &key + ": ";
+        }
+        match display_with(item, visited, runtime.clone(), ctx.clone()) {
+            Result::String(text) => {
+                txt += &text;
+            }
+            res => {
+                visited.pop();
+                return res;
+            }
+        }
+    }
+    visited.pop();
+    txt = txt + " " + close;
+    return Result::String(open.to_string() + &txt[1..]);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::result::ErrorKind;
+    use crate::runtime::test::*;
+
+    #[test]
+    fn builtin_methods() {
+        assert_eq!(text("a = [1, 2]; a.map((x) -> { x * 2 })"), "[ 2, 4 ]");
+        assert_eq!(text("\"a,b\".split(\",\")"), "[ a, b ]");
+    }
+
+    #[test]
+    fn methods_get_self() {
+        assert_eq!(text("o = {\"v\": 1, \"get\": () -> { self::v }}; o.get()"), "1");
+        assert_eq!(
+            text("A = class({\"init\": () -> { self::c = 0 }, \"inc\": () -> { self::c = self::c + 1 }}); a = A.new(); a.inc(); a.inc(); a::c"),
+            "2"
+        );
+    }
+
+    #[test]
+    fn classes_and_inheritance() {
+        assert_eq!(text("A = class({\"init\": (n) -> { self::n = n }}); [A.new(1)::n, A.new(2)::n]"), "[ 1, 2 ]");
+        assert_eq!(
+            text("A = class({\"init\": (n) -> { self::n = n }, \"hi\": () -> { \"A\" }}); B = class({\"hi\": () -> { \"B\" }}, A); b = B.new(2); [b.hi(), b::n]"),
+            "[ B, 2 ]"
+        );
+        assert_eq!(text("A = class({\"hi\": () -> { \"A\" }}); B = class({}, A); B.new().hi()"), "A");
+    }
+
+    #[test]
+    fn missing_methods() {
+        assert_eq!(error_kind("5.nope()"), Some(ErrorKind::NameError));
+        assert_eq!(error_kind("o = {}; o.nope()"), Some(ErrorKind::NameError));
+        assert_eq!(error_kind("o = {\"x\": 1}; o.x()"), Some(ErrorKind::TypeError));
+        assert_eq!(error_kind("class(5)"), Some(ErrorKind::TypeError));
+        assert_eq!(error_kind("A = class({\"init\": (n) -> { self::n = n }}); A.new()"), Some(ErrorKind::ArgumentError));
+    }
+
+    /// Objects with every special method, a and b are equal and smaller than c
+    const POINTS: &str = "P = class({\"init\": (v) -> { self::v = v }, \"__eq__\": (o) -> { self::v == o::v }, \"__lt__\": (o) -> { self::v < o::v }, \
+        \"__str__\": () -> { \"P(\" + string(self::v) + \")\" }, \"__add__\": (o) -> { P.new(self::v + o::v) }, \
+        \"__index__\": (i) -> { self::v * i }, \"__call__\": (x) -> { self::v + x }}); a = P.new(1); b = P.new(1); c = P.new(2); ";
+
+    fn points(script: &str) -> String {
+        return text(&(POINTS.to_string() + script));
+    }
+
+    #[test]
+    fn overloaded_operators() {
+        assert_eq!(points("(a + c)::v"), "3");
+        assert_eq!(points("[c[5], c(3), string(a)]"), "[ 10, 5, P(1) ]");
+        assert_eq!(points("[a == b, a < c, c > a, a != c, a <= b]"), "[ true, true, true, true, true ]");
+    }
+
+    #[test]
+    fn objects_inside_of_containers() {
+        assert_eq!(points("[[a] == [b], [a] < [c], b in [a], c not in [a]]"), "[ true, true, true, true ]");
+        assert_eq!(points("string([a, [c]])"), "[ P(1), [ P(2) ] ]");
+        assert_eq!(points("\"x: ${[a]}\""), "x: [ P(1) ]");
+    }
+
+    #[test]
+    fn reversed_less_than_needs_eq() {
+        let class = "L = class({\"init\": (v) -> { self::v = v }, \"__lt__\": (o) -> { self::v < o }}); ";
+        assert_eq!(text(&(class.to_string() + "[L.new(3) < 5, 5 < L.new(3)]")), "[ true, false ]");
+        assert_eq!(error_kind(&(class.to_string() + "5 < L.new(7)")), Some(ErrorKind::TypeError));
+    }
+
+    #[test]
+    fn call_removed_by_its_arguments() {
+        assert_eq!(
+            error_kind("A = class({\"__call__\": (x) -> { x }}); o = A.new(); f = () -> { o[\"__proto__\"] = {}; 1 }; o(f())"),
+            Some(ErrorKind::TypeError)
+        );
+    }
+
+    #[test]
+    fn operators_which_are_not_overloaded() {
+        assert_eq!(error_kind(&(POINTS.to_string() + "a - c")), Some(ErrorKind::TypeError));
+    }
+}
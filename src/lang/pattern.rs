@@ -0,0 +1,224 @@
+use super::*;
+use crate::ast::Node;
+use crate::context::Context;
+use crate::interpreter::Rule;
+use crate::result::{ErrorKind, Result};
+use crate::runtime::Runtime;
+use std::collections::HashMap;
+
+/// Collects the variables a pattern binds, nothing is assigned if the value does not fit the pattern
+pub fn destructure(
+    pattern: &Node,
+    value: Result,
+    bindings: &mut Vec<(String, Result)>,
+    runtime: Arc<Runtime>,
+    ctx: Arc<Context>,
+) -> Result {
+    // Variables, _ and the error type can hold errors, other patterns can't destructure them
+    if let Rule::ArrayPattern | Rule::DictPattern | Rule::ValuePattern | Rule::RangePattern = pattern.rule() {
+        if let Result::Error(e) = value {
+            return Result::Error(e);
+        }
+    }
+    match pattern.rule() {
+        Rule::Ident => {
+            bindings.push((pattern.content().to_string(), value));
+            return Result::None;
+        }
+        Rule::Wildcard => {
+            return Result::None;
+        }
+        Rule::ArrayPattern => {
+            if let Result::Array(arr) = value {
+                let values = arr.lock().unwrap().clone();
+                return destructure_array(pattern.inner(), values, bindings, runtime, ctx);
+            }
+            return Result::error(ErrorKind::TypeError, "Only arrays can be destructured with [ ], not ".to_string() + &value.typename());
+        }
+        Rule::DictPattern => {
+            if let Result::Dict(map) = value {
+                let map = map.lock().unwrap().clone();
+                return destructure_dict(pattern.inner(), map, bindings, runtime, ctx);
+            }
+            return Result::error(ErrorKind::TypeError, "Only dicts can be destructured with { }, not ".to_string() + &value.typename());
+        }
+        Rule::ValuePattern => {
+            let expected = eval_valuepattern(pattern, runtime, ctx);
+            // none can't be compared, so it is matched by its type
+            if let (Result::None, Result::None) = (&value, &expected) {
+                return Result::None;
+            }
+            if let Result::Bool(true) = equals(&value, &expected) {
+                return Result::None;
+            }
+            return mismatch(&expected.to_string(), &value);
+        }
+        Rule::RangePattern => {
+            let inner = pattern.inner();
+            let low = eval_valuepattern(&inner[0], runtime.clone(), ctx.clone());
+            let high = eval_valuepattern(&inner[2], runtime, ctx);
+            let below = if inner[1].content() == "..=" { smallereq(&value, &high) } else { smaller(&value, &high) };
+            if let (Result::Bool(true), Result::Bool(true)) = (greatereq(&value, &low), below) {
+                return Result::None;
+            }
+            return mismatch(&("a value in ".to_string() + &low.to_string() + inner[1].content() + &high.to_string()), &value);
+        }
+        Rule::TypePattern => {
+            let inner = pattern.inner();
+            if value.typename() != inner[0].content().as_str() {
+                return mismatch(&("a value of type ".to_string() + inner[0].content()), &value);
+            }
+            if let Some(ident) = inner.get(1) {
+                bindings.push((ident.content().to_string(), value));
+            }
+            return Result::None;
+        }
+        _ => {
+            return Result::error(ErrorKind::RuntimeError, "Unknown pattern".to_string());
+        }
+    }
+}
+
+fn mismatch(expected: &str, value: &Result) -> Result {
+    return Result::error(ErrorKind::ValueError, "Expected ".to_string() + expected + ", got " + &value.to_string());
+}
+
+fn eval_valuepattern(pattern: &Node, runtime: Arc<Runtime>, ctx: Arc<Context>) -> Result {
+    let inner = pattern.inner();
+    if let Rule::Minus = inner[0].rule() {
+        return minus(eval(&inner[1], runtime, ctx));
+    }
+    return eval(&inner[0], runtime, ctx);
+}
+
+/// Binds the values in order, a ...rest item takes all values the other items don't need
+pub fn destructure_array(
+    items: &[Node],
+    mut values: Vec<Result>,
+    bindings: &mut Vec<(String, Result)>,
+    runtime: Arc<Runtime>,
+    ctx: Arc<Context>,
+) -> Result {
+    let rests: Vec<usize> = items.iter().enumerate().filter(|(_, item)| item.rule == Rule::RestPattern).map(|(i, _)| i).collect();
+    if rests.len() > 1 {
+        return Result::error(ErrorKind::ValueError, "A pattern may only contain one ...rest".to_string());
+    }
+    let needed = items.len() - rests.len();
+    if values.len() < needed || (rests.is_empty() && values.len() > needed) {
+        let expected = if rests.is_empty() { "" } else { "at least " };
+        return Result::error(ErrorKind::ValueError, "Expected ".to_string() + expected + &needed.to_string() + " values to destructure, got " + &values.len().to_string());
+    }
+    if let Some(&pos) = rests.first() {
+        let tail = values.split_off(values.len() - (items.len() - pos - 1));
+        let rest = values.split_off(pos);
+        values.push(Result::array(rest));
+        values.extend(tail);
+    }
+    for (item, value) in items.iter().zip(values) {
+        let target = if item.rule == Rule::RestPattern { &item.inner()[0] } else { item };
+        if let Result::Error(e) = destructure(target, value, bindings, runtime.clone(), ctx.clone()) {
+            return Result::Error(e);
+        }
+    }
+    return Result::None;
+}
+
+/// Binds the values of the named keys, a ...rest item takes a dict of the other keys
+fn destructure_dict(
+    items: &[Node],
+    mut map: HashMap<String, Result>,
+    bindings: &mut Vec<(String, Result)>,
+    runtime: Arc<Runtime>,
+    ctx: Arc<Context>,
+) -> Result {
+    let mut rest = None;
+    for item in items {
+        match item.rule() {
+            Rule::RestPattern => {
+                rest = Some(item.inner()[0].content().to_string());
+            }
+            Rule::KeyPattern => {
+                let key = eval(&item.inner()[0], runtime.clone(), ctx.clone()).to_string();
+                if let Some(value) = map.remove(&key) {
+                    if let Result::Error(e) = destructure(&item.inner()[1], value, bindings, runtime.clone(), ctx.clone()) {
+                        return Result::Error(e);
+                    }
+                } else {
+                    return Result::error(ErrorKind::KeyError, "Dict does not contain key ".to_string() + &key);
+                }
+            }
+            _ => {
+                if let Some(value) = map.remove(item.content().as_str()) {
+                    bindings.push((item.content().to_string(), value));
+                } else {
+                    return Result::error(ErrorKind::KeyError, "Dict does not contain key ".to_string() + item.content());
+                }
+            }
+        }
+    }
+    if let Some(name) = rest {
+        bindings.push((name, Result::dict(map)));
+    }
+    return Result::None;
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::result::ErrorKind;
+    use crate::runtime::test::*;
+
+    #[test]
+    fn destructuring_assignment() {
+        assert_eq!(text("[a, b] = [1, 2]; a + b"), "3");
+        assert_eq!(text("[h, ...rest] = [1, 2, 3]; [h, rest]"), "[ 1, [ 2, 3 ] ]");
+        assert_eq!(text("[a, [b, c]] = [1, [2, 3]]; c"), "3");
+        assert_eq!(text("p = {\"name\": \"x\", \"age\": 3}; {name, age} = p; [name, age]"), "[ x, 3 ]");
+    }
+
+    #[test]
+    fn values_which_do_not_fit() {
+        assert_eq!(error_kind("[a, b] = [1]"), Some(ErrorKind::ValueError));
+        assert_eq!(error_kind("[a, b] = [1, 2, 3]"), Some(ErrorKind::ValueError));
+        assert_eq!(error_kind("[a, b] = 5"), Some(ErrorKind::TypeError));
+        assert_eq!(error_kind("{name} = {}"), Some(ErrorKind::KeyError));
+        assert_eq!(error_kind("{name} = 5"), Some(ErrorKind::TypeError));
+        assert_eq!(text("a = 0; [a, b] = [1]; a"), "0");
+    }
+
+    #[test]
+    fn for_loops_with_several_variables() {
+        assert_eq!(text("r = []; for k, v in {\"a\": 1} { push(r, [k, v]) }; r"), "[ [ a, 1 ] ]");
+        assert_eq!(text("r = []; for i, x in enumerate([\"a\", \"b\"]) { push(r, [i, x]) }; r"), "[ [ 0, a ], [ 1, b ] ]");
+        assert_eq!(text("r = 0; for k in {\"a\": 1, \"b\": 2} { r = r + 1 }; r"), "2");
+        assert_eq!(error_kind("for a, b in [1] { 0 }"), Some(ErrorKind::TypeError));
+    }
+
+    #[test]
+    fn match_patterns() {
+        assert_eq!(text("match 3 { 1 => \"a\", 2..5 => \"b\", _ => \"c\" }"), "b");
+        assert_eq!(text("match \"s\" { int => \"i\", string => \"s\" }"), "s");
+        assert_eq!(text("match [1, 2, 3] { [a, ...r] => r }"), "[ 2, 3 ]");
+        assert_eq!(text("match {\"x\": 1} { {x} => x }"), "1");
+        assert_eq!(text("match 2 { [a] => a, x => x * 2 }"), "4");
+        assert_eq!(text("x = match 1 { 1 => \"one\" }; x"), "one");
+    }
+
+    #[test]
+    fn match_guards() {
+        assert_eq!(text("match 5 { n if n > 3 => \"big\", _ => \"small\" }"), "big");
+        assert_eq!(text("match true { true if 1 > 0 => \"t\" }"), "t");
+    }
+
+    #[test]
+    fn match_none_and_keyword_prefixes() {
+        assert_eq!(text("match none { none => \"n\" }"), "n");
+        assert_eq!(text("match 1 { none => \"n\", _ => \"o\" }"), "o");
+        assert_eq!(text("match 3 { truex => truex }"), "3");
+        assert_eq!(text("nonex = 1; truex = 2; [nonex, truex]"), "[ 1, 2 ]");
+    }
+
+    #[test]
+    fn no_matching_arm() {
+        assert_eq!(error_kind("match 9 { 1 => 2 }"), Some(ErrorKind::ValueError));
+    }
+}
@@ -2,11 +2,10 @@ use super::*;
 use crate::ast::Node;
 use crate::context::Context;
 use crate::interpreter::Rule;
-use crate::result::Result;
+use crate::result::{ErrorKind, Result};
 use crate::runtime::Runtime;
 use std::process::{Stdio,Command,Child};
-use std::os::unix::io::{FromRawFd, AsRawFd};
-use std::io::{Error, ErrorKind};
+use std::io;
 use std::path::PathBuf;
 
 pub struct Pipe {
@@ -33,13 +32,11 @@ impl Pipe {
             Err(e) => return Pipe::pipe_error(Err(e)),
         };
 
-        let stdio = unsafe{ Stdio::from_raw_fd(stdout.as_raw_fd()) };
-
         Pipe {
             child: Command::new(file)
                     .args(args.as_slice())
                     .stdout(match last{true=>{Stdio::inherit()}false=>{Stdio::piped()}})
-                    .stdin(stdio)
+                    .stdin(Stdio::from(stdout))
                     .spawn(),
         }
 
@@ -52,7 +49,7 @@ impl Pipe {
 
     fn pipe_new_error(error: &str) -> Pipe {
         Pipe {
-            child: Err(Error::new(ErrorKind::Other, error)),
+            child: Err(io::Error::new(io::ErrorKind::Other, error)),
         }
     }
     
@@ -92,7 +89,7 @@ pub fn eval_pipe(inner: &Vec<Node>, runtime: Arc<Runtime>, ctx: Arc<Context>) ->
             }
         }
         else{
-            return Result::Error("System function was not found".to_string());
+            return Result::error(ErrorKind::CommandError, "System function was not found".to_string());
         }
         i+=1;
     }
@@ -111,10 +108,10 @@ pub fn eval_pipe(inner: &Vec<Node>, runtime: Arc<Runtime>, ctx: Arc<Context>) ->
            }
        }
        else{
-           return Result::Error("Commands failed".to_string());
+           return Result::error(ErrorKind::CommandError, "Commands failed".to_string());
        }
    }
    else{
-       return Result::Error("Pipe failed".to_string());
+       return Result::error(ErrorKind::CommandError, "Pipe failed".to_string());
    }
 }
\ No newline at end of file
@@ -1,9 +1,52 @@
 use std::process::{Child, Command};
-use crate::result::Result;
+use crate::result::{ErrorKind, Result};
 use std::path::PathBuf;
 use std::process::Stdio;
 
 pub fn exec(file: PathBuf, args: std::slice::Iter<String>) -> Result{
-    Command::new(file).args(args).stdin(Stdio::inherit()).stdout(Stdio::inherit()).spawn().unwrap().wait();
-    return Result::None;
-}
\ No newline at end of file
+    let name = file.to_string_lossy().to_string();
+    match Command::new(file).args(args).stdin(Stdio::inherit()).stdout(Stdio::inherit()).spawn(){
+        Ok(mut child) => {
+            match child.wait(){
+                Ok(status) if status.success() => {
+                    return Result::None;
+                }
+                Ok(status) => {
+                    let code = status.code().map(|code| code.to_string()).unwrap_or("a signal".to_string());
+                    return Result::error(ErrorKind::CommandError, "Command ".to_string() + &name + " exited with " + &code);
+                }
+                Err(e) => {
+                    return Result::error(ErrorKind::CommandError, "Command ".to_string() + &name + " failed: " + &e.to_string());
+                }
+            }
+        }
+        Err(e) => {
+            return Result::error(ErrorKind::CommandError, "Command ".to_string() + &name + " could not be started: " + &e.to_string());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_commands() {
+        if let Result::Error(e) = exec(PathBuf::from("/nonexistent/command"), Vec::new().iter()) {
+            assert_eq!(e.kind, ErrorKind::CommandError);
+        } else {
+            panic!("the command should not start");
+        }
+    }
+
+    #[test]
+    fn failing_commands() {
+        if let Result::Error(e) = exec(PathBuf::from("false"), Vec::new().iter()) {
+            assert_eq!(e.kind, ErrorKind::CommandError);
+            assert_eq!(e.message, "Command false exited with 1");
+        } else {
+            panic!("the command should fail");
+        }
+        assert!(matches!(exec(PathBuf::from("true"), Vec::new().iter()), Result::None));
+    }
+}
@@ -1,12 +1,12 @@
 use super::super::*;
-use crate::result::Result;
+use crate::result::{ErrorKind, Range, Result};
 
 pub fn print(args: std::slice::Iter<Node>, runtime: Arc<Runtime>, ctx: Arc<Context>) -> Result {
     for arg in args {
         let arg = eval(arg, runtime.clone(), ctx.clone());
         if let Result::None = arg {
         } else {
-            print!("{}", arg.to_string());
+            print!("{}", display(arg, runtime.clone(), ctx.clone()).to_string());
         }
     }
     return Result::None;
@@ -17,7 +17,7 @@ pub fn println(args: std::slice::Iter<Node>, runtime: Arc<Runtime>, ctx: Arc<Con
         let arg = eval(arg, runtime.clone(), ctx.clone());
         if let Result::None = arg {
         } else {
-            println!("{}", arg.to_string());
+            println!("{}", display(arg, runtime.clone(), ctx.clone()).to_string());
         }
     }
     return Result::None;
@@ -38,64 +38,127 @@ pub fn vars(args: &Vec<Node>, runtime: Arc<Runtime>, ctx: Arc<Context>) -> Resul
         }
     }
 
-    return Result::Array(matched);
+    return Result::array(matched);
 }
 
+/// Strings are counted in chars like they are indexed
 pub fn len(input: Result) -> Result {
     if let Result::String(text) = input {
-        return Result::Int(text.len() as i64);
+        return Result::Int(text.chars().count() as i64);
     } else if let Result::Array(ar) = input {
-        return Result::Int(ar.len() as i64);
+        return Result::Int(ar.lock().unwrap().len() as i64);
     } else if let Result::Dict(map) = input {
-        return Result::Int(map.len() as i64);
+        return Result::Int(map.lock().unwrap().len() as i64);
+    } else if let Result::Range(range) = input {
+        if range.len() <= i64::MAX as usize {
+            return Result::Int(range.len() as i64);
+        }
+        return Result::error(ErrorKind::ArithmeticError, "The length of the range overflows the range of ints".to_string());
     } else if let Result::Error(e) = input {
         return Result::Error(e);
     } else {
-        return Result::Error("Len may only be called on arrays or strings".to_string());
+        return Result::error(ErrorKind::TypeError, "Len may only be called on arrays or strings".to_string());
     }
 }
 
-pub fn each(array: Result, function: Result, runtime: Arc<Runtime>, ctx: Arc<Context>) -> Result {
+/// [index, value] pairs, for dicts the keys are the values
+pub fn enumerate(input: Result) -> Result {
+    let values: Vec<Result> = match input {
+        Result::Array(ar) => ar.lock().unwrap().clone(),
+        Result::Dict(map) => map.lock().unwrap().keys().map(|key| Result::String(key.clone())).collect(),
+        Result::String(text) => text.chars().map(|c| Result::String(c.to_string())).collect(),
+        Result::Range(range) => range.iter().map(Result::Int).collect(),
+        Result::Error(e) => {
+            return Result::Error(e);
+        }
+        _ => {
+            return Result::error(ErrorKind::TypeError, "Enumerate may only be called on arrays, dicts, strings and ranges".to_string());
+        }
+    };
+    let pairs = values.into_iter().enumerate().map(|(index, value)| Result::array(vec![Result::Int(index as i64), value]));
+    return Result::array(pairs.collect());
+}
+
+pub fn each(array: Result, function: Result, callsite: Option<&Node>, runtime: Arc<Runtime>, ctx: Arc<Context>) -> Result {
+    if let Result::Error(e) = function {
+        return Result::Error(e);
+    }
     if let Result::Array(ar) = array {
+        // The function may change the array, so it is not locked while iterating
+        let ar = ar.lock().unwrap().clone();
         let mut index = 0 as i64;
         for item in ar {
             let mut itemar = Vec::new();
             itemar.push(item);
             itemar.push(Result::Int(index));
-            exec_func(itemar.iter(), &function, runtime.clone(), ctx.clone());
+            let res = exec_func(itemar.iter(), &function, "<callback of each>", callsite, runtime.clone(), ctx.clone());
+            if let Result::Error(_) = res {
+                if ctx.aborts_on_error() {
+                    return res;
+                }
+            }
             index += 1;
         }
         return Result::None;
     } else if let Result::Dict(map) = array {
+        let map = map.lock().unwrap().clone();
         let mut index = 0 as i64;
         for (key, value) in map {
             let mut itemar = Vec::new();
             itemar.push(Result::String(key));
             itemar.push(value);
             itemar.push(Result::Int(index));
-            exec_func(itemar.iter(), &function, runtime.clone(), ctx.clone());
+            let res = exec_func(itemar.iter(), &function, "<callback of each>", callsite, runtime.clone(), ctx.clone());
+            if let Result::Error(_) = res {
+                if ctx.aborts_on_error() {
+                    return res;
+                }
+            }
             index += 1;
         }
         return Result::None;
+    } else if let Result::Range(range) = array {
+        // The values are created one after another, the range is never turned into an array
+        for (index, item) in range.iter().enumerate() {
+            let itemar = vec![Result::Int(item), Result::Int(index as i64)];
+            let res = exec_func(itemar.iter(), &function, "<callback of each>", callsite, runtime.clone(), ctx.clone());
+            if let Result::Error(_) = res {
+                if ctx.aborts_on_error() {
+                    return res;
+                }
+            }
+        }
+        return Result::None;
     } else if let Result::Error(e) = array {
         return Result::Error(e);
     }
-    return Result::Error("Each may only be called on arrays or strings".to_string());
+    return Result::error(ErrorKind::TypeError, "Each may only be called on arrays or strings".to_string());
 }
 
-pub fn map(array: Result, function: Result, runtime: Arc<Runtime>, ctx: Arc<Context>) -> Result {
+pub fn map(array: Result, function: Result, callsite: Option<&Node>, runtime: Arc<Runtime>, ctx: Arc<Context>) -> Result {
+    if let Result::Error(e) = function {
+        return Result::Error(e);
+    }
     if let Result::Array(ar) = array {
+        let ar = ar.lock().unwrap().clone();
         let mut index = 0 as i64;
         let mut newar = Vec::with_capacity(ar.len());
         for item in ar {
             let mut itemar = Vec::new();
             itemar.push(item);
             itemar.push(Result::Int(index));
-            newar.push(exec_func(itemar.iter(), &function, runtime.clone(), ctx.clone()));
+            let res = exec_func(itemar.iter(), &function, "<callback of map>", callsite, runtime.clone(), ctx.clone());
+            if let Result::Error(_) = res {
+                if ctx.aborts_on_error() {
+                    return res;
+                }
+            }
+            newar.push(res);
             index += 1;
         }
-        return Result::Array(newar);
+        return Result::array(newar);
     } else if let Result::Dict(map) = array {
+        let map = map.lock().unwrap().clone();
         let mut index = 0 as i64;
         let mut newdict = HashMap::with_capacity(map.len());
         for (key, value) in map {
@@ -103,21 +166,191 @@ pub fn map(array: Result, function: Result, runtime: Arc<Runtime>, ctx: Arc<Cont
             itemar.push(Result::String(key.clone()));
             itemar.push(value);
             itemar.push(Result::Int(index));
-            newdict.insert(key, exec_func(itemar.iter(), &function, runtime.clone(), ctx.clone()));
+            let res = exec_func(itemar.iter(), &function, "<callback of map>", callsite, runtime.clone(), ctx.clone());
+            if let Result::Error(_) = res {
+                if ctx.aborts_on_error() {
+                    return res;
+                }
+            }
+            newdict.insert(key, res);
             index += 1;
         }
-        return Result::Dict(newdict);
+        return Result::dict(newdict);
+    } else if let Result::Range(range) = array {
+        let mut newar = Vec::new();
+        for (index, item) in range.iter().enumerate() {
+            let itemar = vec![Result::Int(item), Result::Int(index as i64)];
+            let res = exec_func(itemar.iter(), &function, "<callback of map>", callsite, runtime.clone(), ctx.clone());
+            if let Result::Error(_) = res {
+                if ctx.aborts_on_error() {
+                    return res;
+                }
+            }
+            newar.push(res);
+        }
+        return Result::array(newar);
     } else if let Result::Error(e) = array {
         return Result::Error(e);
     }
-    return Result::Error("Map may only be called on arrays or strings".to_string());
+    return Result::error(ErrorKind::TypeError, "Map may only be called on arrays or strings".to_string());
 }
 
 
+/// Arguments the function is called with for every item, like in map and each
+fn item_args(array: Result) -> Option<Vec<Vec<Result>>> {
+    match array {
+        Result::Array(ar) => {
+            let ar = ar.lock().unwrap().clone();
+            let mut items = Vec::with_capacity(ar.len());
+            for (index, item) in ar.into_iter().enumerate() {
+                items.push(vec![item, Result::Int(index as i64)]);
+            }
+            return Some(items);
+        }
+        Result::Dict(map) => {
+            let map = map.lock().unwrap().clone();
+            let mut items = Vec::with_capacity(map.len());
+            for (index, (key, value)) in map.into_iter().enumerate() {
+                items.push(vec![Result::String(key), value, Result::Int(index as i64)]);
+            }
+            return Some(items);
+        }
+        Result::Range(range) => {
+            return Some(range.iter().enumerate().map(|(index, item)| vec![Result::Int(item), Result::Int(index as i64)]).collect());
+        }
+        _ => {
+            return None;
+        }
+    }
+}
+
+/// Calls the function for every item on a pool of worker threads, the results keep the order of the items.
+/// Every item has its own slot, so the items of a worker which panicked become errors instead of disappearing
+fn exec_parallel(
+    items: &[Vec<Result>],
+    function: &Result,
+    workers: Result,
+    name: &str,
+    callsite: Option<&Node>,
+    runtime: Arc<Runtime>,
+    ctx: Arc<Context>,
+) -> Result {
+    let workers = match workers {
+        Result::None => std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1),
+        Result::Int(i) if i > 0 => i as usize,
+        Result::Error(e) => {
+            return Result::Error(e);
+        }
+        _ => {
+            return Result::error(ErrorKind::ValueError, "The number of workers needs to be a positive int".to_string());
+        }
+    };
+    let next = std::sync::atomic::AtomicUsize::new(0);
+    let slots: Vec<std::sync::Mutex<Option<Result>>> = items.iter().map(|_| std::sync::Mutex::new(None)).collect();
+    std::thread::scope(|scope| {
+        let mut handles = Vec::new();
+        for _ in 0..workers.min(items.len()) {
+            let (next, slots, runtime, ctx) = (&next, &slots, runtime.clone(), ctx.clone());
+            handles.push(scope.spawn(move || loop {
+                let i = next.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                if i >= items.len() {
+                    return;
+                }
+                let res = exec_func(items[i].iter(), function, name, callsite, runtime.clone(), ctx.clone());
+                *slots[i].lock().unwrap() = Some(res);
+            }));
+        }
+        // A panicked worker leaves the slot of its current item empty, the other workers take over the remaining items
+        for handle in handles {
+            let _ = handle.join();
+        }
+    });
+    let results = slots.into_iter().map(|slot| {
+        let res = slot.into_inner().unwrap_or_else(|poisoned| poisoned.into_inner());
+        return res.unwrap_or_else(|| Result::error(ErrorKind::RuntimeError, "The worker thread calling the function for this item panicked".to_string()));
+    });
+    return Result::array(results.collect());
+}
+
+/// Parallel map, an error of one item is stored as its result and does not stop the other items
+pub fn pmap(array: Result, function: Result, workers: Result, callsite: Option<&Node>, runtime: Arc<Runtime>, ctx: Arc<Context>) -> Result {
+    if let Result::Error(e) = function {
+        return Result::Error(e);
+    }
+    if let Result::Dict(map) = &array {
+        // Keys and values are copied under one lock, so callbacks removing keys from the dict can not make them missing
+        let entries: Vec<(String, Result)> = map.lock().unwrap().clone().into_iter().collect();
+        let keys: Vec<String> = entries.iter().map(|(key, _)| key.clone()).collect();
+        let items: Vec<Vec<Result>> = entries.into_iter().enumerate().map(|(index, (key, value))| {
+            return vec![Result::String(key), value, Result::Int(index as i64)];
+        }).collect();
+        if let Result::Array(values) = exec_parallel(&items, &function, workers, "<callback of pmap>", callsite, runtime, ctx) {
+            let values = values.lock().unwrap().clone();
+            return Result::dict(keys.into_iter().zip(values).collect());
+        }
+    } else if let Some(items) = item_args(array.clone()) {
+        return exec_parallel(&items, &function, workers, "<callback of pmap>", callsite, runtime, ctx);
+    } else if let Result::Error(e) = array {
+        return Result::Error(e);
+    }
+    return Result::error(ErrorKind::TypeError, "Pmap may only be called on arrays or dicts".to_string());
+}
+
+/// Parallel each, returns [index, error] pairs of all items which failed, for dicts [key, error]
+pub fn peach(array: Result, function: Result, workers: Result, callsite: Option<&Node>, runtime: Arc<Runtime>, ctx: Arc<Context>) -> Result {
+    if let Result::Error(e) = function {
+        return Result::Error(e);
+    }
+    let isdict = matches!(array, Result::Dict(_));
+    if let Some(items) = item_args(array.clone()) {
+        if let Result::Array(results) = exec_parallel(&items, &function, workers, "<callback of peach>", callsite, runtime, ctx) {
+            let results = results.lock().unwrap().clone();
+            let mut failed = Vec::new();
+            for (item, res) in items.into_iter().zip(results) {
+                if let Result::Error(_) = res {
+                    // Items of dicts start with their key, the other items end with their index
+                    let position = if isdict { item[0].clone() } else { item.last().unwrap().clone() };
+                    failed.push(Result::array(vec![position, res]));
+                }
+            }
+            return Result::array(failed);
+        }
+    } else if let Result::Error(e) = array {
+        return Result::Error(e);
+    }
+    return Result::error(ErrorKind::TypeError, "Peach may only be called on arrays or dicts".to_string());
+}
+
+/// Appends the value to the array itself, not to a copy
+pub fn push(array: Result, value: Result) -> Result {
+    if let Result::Array(ar) = array {
+        ar.lock().unwrap().push(value);
+        return Result::None;
+    } else if let Result::Error(e) = array {
+        return Result::Error(e);
+    }
+    return Result::error(ErrorKind::TypeError, "Push may only be called on arrays".to_string());
+}
+
+pub fn pop(array: Result) -> Result {
+    if let Result::Array(ar) = array {
+        if let Some(value) = ar.lock().unwrap().pop() {
+            return value;
+        }
+        return Result::error(ErrorKind::IndexError, "Pop may not be called on an empty array".to_string());
+    } else if let Result::Error(e) = array {
+        return Result::Error(e);
+    }
+    return Result::error(ErrorKind::TypeError, "Pop may only be called on arrays".to_string());
+}
 
 pub fn join(array: Result, joinstring: Result, runtime: Arc<Runtime>, ctx: Arc<Context>) -> Result {
     if let Result::Array(ar) = array {
         if let Result::String(text) = joinstring{
+            let ar = ar.lock().unwrap().clone();
+            if ar.is_empty(){
+                return Result::String(String::new());
+            }
             let mut res = String::new();
             for item in ar{
                 res += text.as_str();
@@ -126,71 +359,232 @@ pub fn join(array: Result, joinstring: Result, runtime: Arc<Runtime>, ctx: Arc<C
             res.replace_range(0..text.len(), "");
             return Result::String(res);
         }
+        else if let Result::Error(e) = joinstring{
+            return Result::Error(e);
+        }
         else{
-            return Result::Error("Array may only be joined with a string".to_string());
+            return Result::error(ErrorKind::TypeError, "Array may only be joined with a string".to_string());
         }
     } else if let Result::Error(e) = array {
         return Result::Error(e);
     }
-    return Result::Error("Join may only be called on an array and a string".to_string());
+    return Result::error(ErrorKind::TypeError, "Join may only be called on an array and a string".to_string());
+}
+
+/// Splits a string at each separator, an empty separator splits it into chars
+pub fn split(text: Result, separator: Result) -> Result {
+    if let (Result::String(text), Result::String(separator)) = (&text, &separator) {
+        if separator.is_empty() {
+            return Result::array(text.chars().map(|c| Result::String(c.to_string())).collect());
+        }
+        return Result::array(text.split(separator.as_str()).map(|part| Result::String(part.to_string())).collect());
+    }
+    return string_error("Split", text, separator);
+}
+
+pub fn replace(text: Result, pattern: Result, replacement: Result) -> Result {
+    if let Result::Error(e) = replacement {
+        return Result::Error(e);
+    }
+    if let (Result::String(text), Result::String(pattern)) = (&text, &pattern) {
+        return Result::String(text.replace(pattern.as_str(), &replacement.to_string()));
+    }
+    return string_error("Replace", text, pattern);
+}
+
+pub fn starts_with(text: Result, prefix: Result) -> Result {
+    if let (Result::String(text), Result::String(prefix)) = (&text, &prefix) {
+        return Result::Bool(text.starts_with(prefix.as_str()));
+    }
+    return string_error("Starts_with", text, prefix);
+}
+
+pub fn ends_with(text: Result, suffix: Result) -> Result {
+    if let (Result::String(text), Result::String(suffix)) = (&text, &suffix) {
+        return Result::Bool(text.ends_with(suffix.as_str()));
+    }
+    return string_error("Ends_with", text, suffix);
+}
+
+/// Applies a function to the text of a string, like trim or to_uppercase
+pub fn map_string(text: Result, name: &str, function: fn(&str) -> String) -> Result {
+    if let Result::String(text) = text {
+        return Result::String(function(&text));
+    } else if let Result::Error(e) = text {
+        return Result::Error(e);
+    }
+    return Result::error(ErrorKind::TypeError, name.to_string() + " may only be called on strings, not " + &text.typename());
+}
+
+fn string_error(name: &str, text: Result, arg: Result) -> Result {
+    if let Result::Error(e) = text {
+        return Result::Error(e);
+    } else if let Result::Error(e) = arg {
+        return Result::Error(e);
+    }
+    return Result::error(ErrorKind::TypeError, name.to_string() + " may only be called with strings, not " + &text.typename() + " and " + &arg.typename());
+}
+
+/// Position of an index, negative indices count from the end
+fn position(i: i64, len: usize) -> Option<usize> {
+    let pos = if i < 0 { i + len as i64 } else { i };
+    if pos < 0 || pos >= len as i64 {
+        return None;
+    }
+    return Some(pos as usize);
+}
+
+fn index_error(i: i64, len: usize) -> Result {
+    return Result::error(ErrorKind::IndexError, "Index ".to_string() + &i.to_string() + " is out of bounds for length " + &len.to_string());
+}
+
+/// Bound of a slice, negative bounds count from the end and bounds outside of the array are clamped
+fn slice_bound(i: i64, step: i64, inclusive: bool, len: usize) -> i64 {
+    let len = len as i64;
+    let mut i = if i < 0 { i + len } else { i };
+    if inclusive {
+        i = i.saturating_add(step.signum());
+    }
+    if step > 0 {
+        return i.max(0).min(len);
+    }
+    return i.max(-1).min(len - 1);
+}
+
+/// Positions selected by a range used as slice
+fn slice_positions(range: &Range, len: usize) -> Vec<usize> {
+    let positions = Range {
+        start: slice_bound(range.start, range.step, false, len),
+        end: slice_bound(range.end, range.step, range.inclusive, len),
+        step: range.step,
+        inclusive: false,
+    };
+    return positions.iter().map(|i| i as usize).collect();
 }
 
 pub fn get_index(input: &Result, index: Result) -> Result {
     if let Result::Array(arr) = input {
         if let Result::Int(i) = index {
-            if i >= arr.len() as i64{
-                return Result::Error("Index out of bounds: ".to_string() + &i.to_string() + " >= " + &arr.len().to_string());
+            let arr = arr.lock().unwrap();
+            if let Some(pos) = position(i, arr.len()) {
+                return arr[pos].clone();
             }
-            return arr[i as usize].clone();
+            return index_error(i, arr.len());
+        } else if let Result::Range(range) = index {
+            let arr = arr.lock().unwrap();
+            let slice = slice_positions(&range, arr.len()).into_iter().map(|i| arr[i].clone()).collect();
+            return Result::array(slice);
         } else if let Result::Error(e) = index {
             return Result::Error(e);
         } else {
-            return Result::Error("Array may only be indexed by an int".to_string());
+            return Result::error(ErrorKind::TypeError, "Array may only be indexed by an int".to_string());
         }
     } else if let Result::Dict(map) = input {
         if let Result::String(i) = index {
+            let map = map.lock().unwrap();
             if !map.contains_key(&i){
-                return Result::Error("Dict does not contain key ".to_string() + &i);
+                return Result::error(ErrorKind::KeyError, "Dict does not contain key ".to_string() + &i);
             }
             return map[&i].clone();
         } else if let Result::Error(e) = index {
             return Result::Error(e);
         } else {
-            return Result::Error("Dict may only be indexed by a string".to_string());
+            return Result::error(ErrorKind::TypeError, "Dict may only be indexed by a string".to_string());
         }
     } else if let Result::String(text) = input {
+        let chars: Vec<char> = text.chars().collect();
+        if let Result::Int(i) = index {
+            if let Some(pos) = position(i, chars.len()) {
+                return Result::String(chars[pos].to_string());
+            }
+            return index_error(i, chars.len());
+        } else if let Result::Range(range) = index {
+            return Result::String(slice_positions(&range, chars.len()).into_iter().map(|i| chars[i]).collect());
+        } else if let Result::Error(e) = index {
+            return Result::Error(e);
+        } else {
+            return Result::error(ErrorKind::TypeError, "String may only be indexed by an int or a range".to_string());
+        }
+    } else if let Result::Range(range) = input {
         if let Result::Int(i) = index {
-            return Result::String(text.chars().skip(i as usize).take(1).collect());
+            if let Some(value) = range.get(i) {
+                return Result::Int(value);
+            }
+            return index_error(i, range.len());
+        } else if let Result::Range(slice) = index {
+            let positions = slice_positions(&slice, range.len());
+            // Positions are inside of the range, so only the intermediate product may overflow an i64
+            return Result::array(
+                positions.into_iter().map(|i| Result::Int((range.start as i128 + i as i128 * range.step as i128) as i64)).collect(),
+            );
         } else if let Result::Error(e) = index {
             return Result::Error(e);
         } else {
-            return Result::Error("Array may only be index by an int".to_string());
+            return Result::error(ErrorKind::TypeError, "Range may only be indexed by an int or a range".to_string());
         }
     }
     return Result::None;
 }
 
+/// Changes the array or dict in place, so every variable sharing it sees the new value
 pub fn set_index(input: Result, index: Result, value: Result) -> Result {
-    if let Result::Array(mut arr) = input {
+    if let Result::Array(shared) = &input {
         if let Result::Int(i) = index {
-            arr[i as usize] = value;
-            return Result::Array(arr);
+            let mut arr = shared.lock().unwrap();
+            if let Some(pos) = position(i, arr.len()) {
+                arr[pos] = value;
+                drop(arr);
+                return input;
+            }
+            return index_error(i, arr.len());
+        } else if let Result::Range(range) = index {
+            return set_slice(input.clone(), &range, value);
         } else if let Result::Error(e) = index {
             return Result::Error(e);
         } else {
-            return Result::Error("Array may only be indexed by an int".to_string());
+            return Result::error(ErrorKind::TypeError, "Array may only be indexed by an int".to_string());
         }
-    } else if let Result::Dict(mut map) = input {
+    } else if let Result::Dict(shared) = &input {
         if let Result::String(i) = index {
-            map.insert(i, value);
-            return Result::Dict(map);
+            shared.lock().unwrap().insert(i, value);
+            return input;
         } else if let Result::Error(e) = index {
             return Result::Error(e);
         } else {
-            return Result::Error("Dict may only be indexed by a string".to_string());
+            return Result::error(ErrorKind::TypeError, "Dict may only be indexed by a string".to_string());
         }
+    } else if let Result::Error(e) = input {
+        return Result::Error(e);
     }
-    return Result::None;
+    return Result::error(ErrorKind::TypeError, "Only values of arrays and dicts may be assigned, not of ".to_string() + &input.typename());
+}
+
+/// Replaces the elements of a slice, slices without a step may change the length of the array
+fn set_slice(input: Result, range: &Range, value: Result) -> Result {
+    let values = if let Result::Array(values) = value {
+        // The assigned array may be the sliced array itself
+        values.lock().unwrap().clone()
+    } else if let Result::Error(e) = value {
+        return Result::Error(e);
+    } else {
+        return Result::error(ErrorKind::TypeError, "Only arrays may be assigned to slices, not ".to_string() + &value.typename());
+    };
+    if let Result::Array(shared) = &input {
+        let mut arr = shared.lock().unwrap();
+        let positions = slice_positions(range, arr.len());
+        if range.step == 1 {
+            let first = slice_bound(range.start, 1, false, arr.len()) as usize;
+            let count = positions.len();
+            arr.splice(first..first + count, values);
+        } else if positions.len() != values.len() {
+            return Result::error(ErrorKind::ValueError, "Can't assign ".to_string() + &values.len().to_string() + " values to a slice of length " + &positions.len().to_string());
+        } else {
+            for (pos, value) in positions.into_iter().zip(values) {
+                arr[pos] = value;
+            }
+        }
+    }
+    return input;
 }
 
 //Casting
@@ -203,13 +597,20 @@ pub fn cast_int(input: Result) -> Result {
             return Result::Int(f as i64);
         }
         Result::String(t) => {
-            return Result::Int(t.parse::<i64>().unwrap());
+            if let Ok(i) = t.trim().parse::<i64>() {
+                return Result::Int(i);
+            }
+            return Result::error(ErrorKind::ValueError, "Could not cast \"".to_string() + &t + "\" to int");
+        }
+        Result::Error(e) => {
+            return Result::Error(e);
         }
         Result::Bool(b) => {
             return Result::Int(if b { 1 } else { 0 });
         }
         _ => {
-            return Result::Error(
+            return Result::error(
+                ErrorKind::TypeError,
                 "May not cast type ".to_string() + input.typename().as_str() + " to int",
             );
         }
@@ -225,13 +626,20 @@ pub fn cast_float(input: Result) -> Result {
             return Result::Float(f);
         }
         Result::String(t) => {
-            return Result::Float(t.parse::<f64>().unwrap());
+            if let Ok(f) = t.trim().parse::<f64>() {
+                return Result::Float(f);
+            }
+            return Result::error(ErrorKind::ValueError, "Could not cast \"".to_string() + &t + "\" to float");
+        }
+        Result::Error(e) => {
+            return Result::Error(e);
         }
         Result::Bool(b) => {
             return Result::Float(if b { 1.0 } else { 0.0 });
         }
         _ => {
-            return Result::Error(
+            return Result::error(
+                ErrorKind::TypeError,
                 "May not cast type ".to_string() + input.typename().as_str() + " to float",
             );
         }
@@ -245,15 +653,130 @@ pub fn cast_string(input: Result) -> Result {
 pub fn cast_bool(input: Result) -> Result {
     match input {
         Result::String(t) => {
-            return Result::Bool(t.parse::<bool>().unwrap());
+            if let Ok(b) = t.trim().parse::<bool>() {
+                return Result::Bool(b);
+            }
+            return Result::error(ErrorKind::ValueError, "Could not cast \"".to_string() + &t + "\" to bool");
+        }
+        Result::Error(e) => {
+            return Result::Error(e);
         }
         Result::Bool(b) => {
             return Result::Bool(b);
         }
         _ => {
-            return Result::Error(
+            return Result::error(
+                ErrorKind::TypeError,
                 "May not cast type ".to_string() + input.typename().as_str() + " to bool",
             );
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::result::ErrorKind;
+    use crate::runtime::test::*;
+
+    #[test]
+    fn casts_of_invalid_strings() {
+        assert_eq!(text("int(\"42\")"), "42");
+        assert_eq!(error_kind("int(\"abc\")"), Some(ErrorKind::ValueError));
+        assert_eq!(error_kind("float(\"x\")"), Some(ErrorKind::ValueError));
+        assert_eq!(error_kind("bool(\"x\")"), Some(ErrorKind::ValueError));
+    }
+
+    #[test]
+    fn set_index_out_of_bounds() {
+        assert_eq!(text("a = [1, 2]; a[1] = 3; a"), "[ 1, 3 ]");
+        assert_eq!(error_kind("a = [1]; a[5] = 2"), Some(ErrorKind::IndexError));
+        assert_eq!(error_kind("a = [1]; a[-5] = 2"), Some(ErrorKind::IndexError));
+    }
+
+    #[test]
+    fn pmap_keeps_the_input_order() {
+        assert_eq!(text("pmap([1, 2, 3], (x) -> { x * 2 }, workers=2)"), "[ 2, 4, 6 ]");
+        assert_eq!(text("pmap(0..200, (x) -> { x * 3 }, workers=7) == map(0..200, (x) -> { x * 3 })"), "true");
+        assert_eq!(text("pmap({\"a\": 1}, (k, v) -> { v + 1 })"), "{ a: 2 }");
+        assert_eq!(text("d = {\"a\": 1, \"b\": 2}; r = pmap(d, (k, v) -> { d[\"a\"] = 0; d[\"c\"] = 3; v * 10 }, workers=1); r == {\"a\": 10, \"b\": 20}"), "true");
+    }
+
+    #[test]
+    fn pmap_keeps_errors_in_their_slot() {
+        assert_eq!(text("r = pmap([1, 0, 2], (x) -> { 2 / x }); [r[0], error_kind(r[1]), r[2]]"), "[ 2, ArithmeticError, 1 ]");
+    }
+
+    #[test]
+    fn peach_collects_errors_with_their_position() {
+        assert_eq!(text("peach([1, 2], (x) -> { x })"), "[]");
+        assert_eq!(text("r = peach([1, 0, 2], (x) -> { 2 / x }); [len(r), r[0][0], error_kind(r[0][1])]"), "[ 1, 1, ArithmeticError ]");
+        assert_eq!(text("r = peach({\"a\": 1, \"b\": 0}, (k, v) -> { 2 / v }); r[0][0]"), "b");
+    }
+
+    #[test]
+    fn invalid_arguments_of_pmap() {
+        assert_eq!(error_kind("pmap([1], (x) -> { x }, workers=0)"), Some(ErrorKind::ValueError));
+        assert_eq!(error_kind("pmap(5, (x) -> { x })"), Some(ErrorKind::TypeError));
+    }
+
+    #[test]
+    fn slices_of_arrays() {
+        assert_eq!(text("a = [1, 2, 3, 4]; [a[-1], a[1..3], a[..-1], a[2..], a[..]]"), "[ 4, [ 2, 3 ], [ 1, 2, 3 ], [ 3, 4 ], [ 1, 2, 3, 4 ] ]");
+        assert_eq!(text("a = [0, 1, 2, 3, 4, 5]; [a[0..6..2], a[2..0..-1]]"), "[ [ 0, 2, 4 ], [ 2, 1 ] ]");
+        assert_eq!(text("a = [1, 2]; [a[0..10], a[3..1]]"), "[ [ 1, 2 ], [] ]");
+    }
+
+    #[test]
+    fn slices_of_strings() {
+        assert_eq!(text("s = \"hello\"; [s[-1], s[1..3], s[2..], s[..-1]]"), "[ o, el, llo, hell ]");
+        assert_eq!(text("\"\u{e4}bc\"[0..2]"), "\u{e4}b");
+    }
+
+    #[test]
+    fn length_of_strings_counts_chars() {
+        assert_eq!(text("s = \"h\u{e9}llo\"; [len(s), s.len(), s[len(s) - 1]]"), "[ 5, 5, o ]");
+    }
+
+    #[test]
+    fn invalid_indices() {
+        assert_eq!(error_kind("a = [1, 2]; a[-3]"), Some(ErrorKind::IndexError));
+        assert_eq!(error_kind("\"abc\"[5]"), Some(ErrorKind::IndexError));
+        assert_eq!(error_kind("a = [1, 2]; a[0..4..0]"), Some(ErrorKind::ValueError));
+    }
+
+    #[test]
+    fn slice_assignment() {
+        assert_eq!(text("a = [1, 2, 3]; a[0..2] = [9, 9, 9]; a"), "[ 9, 9, 9, 3 ]");
+        assert_eq!(text("a = [1, 2, 3]; a[0..3..2] = [7, 8]; a"), "[ 7, 2, 8 ]");
+        assert_eq!(error_kind("a = [1, 2]; a[0..1] = 5"), Some(ErrorKind::TypeError));
+        assert_eq!(error_kind("a = [1, 2, 3]; a[0..3..2] = [7]"), Some(ErrorKind::ValueError));
+    }
+
+    #[test]
+    fn ranges() {
+        assert_eq!(text("n = 3; map(0..n+1, (x) -> { x })"), "[ 0, 1, 2, 3 ]");
+        assert_eq!(text("map(-2..=2, (x) -> { x })"), "[ -2, -1, 0, 1, 2 ]");
+        assert_eq!(text("[len(0..10..3), (0..10)[3], 5 in 0..10..5, 6 in 0..10..5]"), "[ 4, 3, true, false ]");
+        assert_eq!(text("linspace(0, 1, 5)"), "[ 0, 0.25, 0.5, 0.75, 1 ]");
+        assert_eq!(
+            text("r = 9223372036854775800..=9223372036854775807; [len(r), 9223372036854775807 in r, len(map(r, (x) -> { x }))]"),
+            "[ 8, true, 8 ]"
+        );
+    }
+
+    #[test]
+    fn slices_of_ranges_near_the_bounds_of_ints() {
+        assert_eq!(
+            text("x = (0 - 9223372036854775807 - 1)..9223372036854775807..4611686018427387904; x[0..10]"),
+            "[ -9223372036854775808, -4611686018427387904, 0, 4611686018427387904 ]"
+        );
+        assert_eq!(text("(0..10..3)[1..]"), "[ 3, 6, 9 ]");
+    }
+
+    #[test]
+    fn invalid_ranges() {
+        assert_eq!(error_kind("0..10..0"), Some(ErrorKind::ValueError));
+        assert_eq!(error_kind("(0..10)[20]"), Some(ErrorKind::IndexError));
+        assert_eq!(error_kind("m = -9223372036854775807 - 1; len(m..9223372036854775807)"), Some(ErrorKind::ArithmeticError));
+    }
+}
@@ -16,11 +16,41 @@ mod interpreter;
 
 fn main() -> Result<()> {
     let matches = args::parse_args();
-    let runtime = runtime::Runtime::new();
-    if let Some(path) = matches.value_of("INPUT") {
-        run_file(runtime, path)?;
+    let strict = matches.is_present("strict");
+    if let Some(command) = matches.value_of("command") {
+        run_command(make_runtime(runtime::Runtime::new_headless(), strict), command)?;
+    } else if let Some(path) = matches.value_of("INPUT") {
+        run_file(make_runtime(runtime::Runtime::new_headless(), strict), path)?;
     } else {
-        main_loop(runtime)?;
+        main_loop(make_runtime(runtime::Runtime::new(), strict))?;
+    }
+    Ok(())
+}
+
+fn make_runtime(runtime: Arc<runtime::Runtime>, strict: bool) -> Arc<runtime::Runtime> {
+    if strict {
+        runtime
+            .basectx
+            .set_var("STRICT", result::Result::Bool(true));
+    }
+    return runtime;
+}
+
+fn run_command(runtime: Arc<runtime::Runtime>, command: &str) -> Result<()> {
+    let res = match runtime.clone().run(command.to_string()) {
+        Ok(res) => res,
+        Err(e) => result::Result::error(result::ErrorKind::ParseError, e.to_string()),
+    };
+    match res {
+        result::Result::None => {}
+        result::Result::Error(_) => {
+            eprintln!("{}", res.to_string());
+            std::process::exit(1);
+        }
+        _ => runtime.clone().print_result(&res),
+    }
+    if runtime.aborted() {
+        std::process::exit(1);
     }
     Ok(())
 }
@@ -28,14 +58,24 @@ fn main() -> Result<()> {
 fn run_file(runtime: Arc<runtime::Runtime>, path: &str) -> Result<()> {
     let path = std::fs::canonicalize(std::path::Path::new(path))?;
     std::env::set_current_dir(path.parent().unwrap())?;
-    runtime.exec_file(path.as_path())?;
+    let res = runtime.clone().run_file(path.as_path())?;
+    match res {
+        result::Result::Error(_) if runtime.is_strict() => {
+            eprintln!("{}", res.to_string());
+            std::process::exit(1);
+        }
+        _ => runtime.clone().print_result(&res),
+    }
+    if runtime.aborted() {
+        std::process::exit(1);
+    }
     Ok(())
 }
 
 fn main_loop(runtime: Arc<runtime::Runtime>) -> Result<()> {
     runtime.print_start()?;
     io::stdout().flush()?;
-    let interface = &runtime.interface;
+    let interface = runtime.interface.clone().unwrap();
     loop {
         let lineresult = interface.read_line()?;
         match lineresult {
@@ -43,7 +83,9 @@ fn main_loop(runtime: Arc<runtime::Runtime>) -> Result<()> {
                 let line = line.trim().to_string();
                 if !line.is_empty() {
                     interface.add_history_unique(line.clone());
-                    runtime.clone().exec(line)?;
+                    if let Err(e) = runtime.clone().exec(line) {
+                        result::Result::error(result::ErrorKind::ParseError, e.to_string()).print();
+                    }
                 }
                 runtime.print_end()?;
                 runtime.print_start()?;
@@ -1,11 +1,101 @@
 use crate::ast::Node;
+use crate::context::Env;
 use std::collections::HashMap;
+use std::fmt;
+use std::io::IsTerminal;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender, TryRecvError};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
 
 #[derive(Clone)]
 #[derive(Debug)]
 pub struct Parameter{
     pub name: String,
-    pub defaultvalue: Option<Result>
+    /// Expression of the default value, it is evaluated at each call and can use the parameters before it
+    pub default: Option<Node>,
+    /// A ...rest parameter gets an array of the remaining positional arguments
+    pub variadic: bool
+}
+
+
+#[derive(Clone)]
+#[derive(Debug)]
+#[derive(PartialEq)]
+// The kinds are named like the errors shown to the user
+#[allow(clippy::enum_variant_names)]
+pub enum ErrorKind{
+    RuntimeError,
+    TypeError,
+    ValueError,
+    ArgumentError,
+    IndexError,
+    KeyError,
+    NameError,
+    ArithmeticError,
+    IOError,
+    ParseError,
+    UserError,
+    CommandError
+}
+
+#[derive(Clone)]
+#[derive(Debug)]
+pub struct Location{
+    pub file: String,
+    pub line: usize,
+    pub column: usize
+}
+
+/// Call site of a user function an error escaped from
+#[derive(Clone)]
+#[derive(Debug)]
+pub struct Frame{
+    pub function: String,
+    pub location: Option<Location>
+}
+
+#[derive(Clone)]
+#[derive(Debug)]
+pub struct ErrorValue{
+    pub kind: ErrorKind,
+    pub message: String,
+    pub location: Option<Location>,
+    /// Value attached to the error by throw
+    pub payload: Option<Box<Result>>,
+    pub trace: Vec<Frame>
+}
+
+/// Handle of a statement which was started with async on another thread
+#[derive(Clone)]
+#[derive(Debug)]
+pub struct Task{
+    pub id: usize,
+    pub handle: Arc<Mutex<Option<JoinHandle<Result>>>>,
+    /// Result of the statement, set by the first await
+    pub result: Arc<Mutex<Option<Result>>>
+}
+
+/// Both ends of a channel, copies of a channel share the same queue.
+/// The ends are kept together because scripts only have one channel value, so close() stops sending for every copy
+#[derive(Clone)]
+#[derive(Debug)]
+pub struct Channel{
+    /// None once the channel was closed
+    pub sender: Arc<Mutex<Option<Sender<Result>>>>,
+    pub receiver: Arc<Mutex<Receiver<Result>>>
+}
+
+/// Counts from start to end, ranges are not turned into arrays for loops, indexing or len
+#[derive(Clone)]
+#[derive(Debug)]
+pub struct Range{
+    pub start: i64,
+    pub end: i64,
+    /// Negative steps count down
+    pub step: i64,
+    /// Whether end is part of the range, set for `..=`
+    pub inclusive: bool
 }
 
 
@@ -14,27 +104,362 @@ pub struct Parameter{
 pub enum Result{
     None,
     Return(Box<Result>),
-    Error(String),
+    Break(Box<Result>),
+    Continue,
+    Error(ErrorValue),
     String(String),
-    Array(Vec<Result>),
-    Dict(HashMap<String,Result>),
+    /// Arrays and dicts are shared between all variables they are assigned to
+    Array(Arc<Mutex<Vec<Result>>>),
+    Dict(Arc<Mutex<HashMap<String,Result>>>),
+    /// The block and parameters are shared by all copies of a function, calls don't copy them
     Function{
-        block: Node,
-        parameters: Vec<Parameter>,
-        vars: HashMap<String, Result>
-    },
-    Range{
-        start: usize,
-        end: usize,
+        block: Arc<Node>,
+        parameters: Arc<Vec<Parameter>>,
+        /// Context the function was defined in, it is captured by reference
+        env: Env
     },
+    Range(Range),
+    Task(Task),
+    Channel(Channel),
     Bool(bool),
     Int(i64),
     Float(f64)
 }
 
 
+impl fmt::Display for Location{
+
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result{
+        return write!(f, "{}:{}:{}", self.file, self.line, self.column);
+    }
+}
+
+
+impl Range{
+
+    /// End of the range without end, it is moved by one step for inclusive ranges.
+    /// It is an i128, as the end of an inclusive range may be the largest or smallest i64
+    fn exclusive_end(&self) -> i128{
+        if self.inclusive{
+            return self.end as i128 + self.step.signum() as i128;
+        }
+        return self.end as i128;
+    }
+
+    /// Stops before a value would overflow, such a value is past the end anyways
+    pub fn iter(&self) -> impl Iterator<Item = i64>{
+        let (end, step, inclusive) = (self.end, self.step, self.inclusive);
+        return std::iter::successors(Some(self.start), move |i| i.checked_add(step))
+            .take_while(move |i| (if step > 0 { *i < end } else { *i > end }) || (inclusive && *i == end));
+    }
+
+    pub fn len(&self) -> usize{
+        let distance = (self.exclusive_end() - self.start as i128) * self.step.signum() as i128;
+        if distance <= 0{
+            return 0;
+        }
+        let step = self.step.abs() as i128;
+        return ((distance + step - 1) / step).min(usize::MAX as i128) as usize;
+    }
+
+    /// Value at the index, negative indices count from the end
+    pub fn get(&self, i: i64) -> Option<i64>{
+        let len = self.len() as i64;
+        let i = if i < 0 { i + len } else { i };
+        if i < 0 || i >= len{
+            return None;
+        }
+        return Some((self.start as i128 + i as i128 * self.step as i128) as i64);
+    }
+
+    pub fn contains(&self, value: i64) -> bool{
+        let (low, high) = if self.step > 0 {
+            (self.start as i128, self.exclusive_end())
+        } else {
+            (self.exclusive_end() + 1, self.start as i128 + 1)
+        };
+        let value = value as i128;
+        return value >= low && value < high && (value - self.start as i128) % self.step as i128 == 0;
+    }
+}
+
+impl Task{
+
+    pub fn is_running(&self) -> bool{
+        if let Some(handle) = &*self.handle.lock().unwrap(){
+            return !handle.is_finished();
+        }
+        return false;
+    }
+
+    /// Waits for the task to finish, awaiting a task multiple times returns the same result
+    pub fn join(&self) -> Result{
+        let mut handle = self.handle.lock().unwrap();
+        if let Some(handle) = handle.take(){
+            let res = match handle.join(){
+                Ok(res) => res,
+                Err(_) => Result::error(ErrorKind::RuntimeError, "Task ".to_string() + &self.id.to_string() + " panicked")
+            };
+            *self.result.lock().unwrap() = Some(res);
+        }
+        return self.result.lock().unwrap().clone().unwrap_or(Result::None);
+    }
+}
+
+
+impl Channel{
+
+    pub fn new() -> Channel{
+        let (sender, receiver) = mpsc::channel();
+        return Channel{
+            sender: Arc::new(Mutex::new(Some(sender))),
+            receiver: Arc::new(Mutex::new(receiver))
+        };
+    }
+
+    pub fn send(&self, value: Result) -> Result{
+        if let Some(sender) = &*self.sender.lock().unwrap(){
+            if let Ok(_) = sender.send(value){
+                return Result::None;
+            }
+        }
+        return Result::error(ErrorKind::RuntimeError, "Channel is closed".to_string());
+    }
+
+    /// Blocks until a value is received, returns None once the channel is closed and empty
+    pub fn recv(&self) -> Option<Result>{
+        return self.receiver.lock().unwrap().recv().ok();
+    }
+
+    /// Does not block, a receiver waiting on another thread counts as an empty channel
+    pub fn try_recv(&self) -> std::result::Result<Result, TryRecvError>{
+        if let Ok(receiver) = self.receiver.try_lock(){
+            return receiver.try_recv();
+        }
+        return Err(TryRecvError::Empty);
+    }
+
+    /// Waiting for another receiver to finish counts towards the timeout, a deadline too far away for an Instant never ends
+    pub fn recv_timeout(&self, timeout: Duration) -> std::result::Result<Result, RecvTimeoutError>{
+        let deadline = match Instant::now().checked_add(timeout){
+            Some(deadline) => deadline,
+            None => {
+                return self.recv().ok_or(RecvTimeoutError::Disconnected);
+            }
+        };
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if let Ok(receiver) = self.receiver.try_lock(){
+                return receiver.recv_timeout(remaining);
+            }
+            if remaining.is_zero(){
+                return Err(RecvTimeoutError::Timeout);
+            }
+            std::thread::sleep(remaining.min(Duration::from_millis(1)));
+        }
+    }
+
+    /// Values which were already sent can still be received
+    pub fn close(&self){
+        self.sender.lock().unwrap().take();
+    }
+}
+
+
+/// Error text without any escape codes, e.g. `file:1:5: TypeError: message`
+impl fmt::Display for ErrorValue{
+
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result{
+        if let Some(location) = &self.location{
+            write!(f, "{}: ", location)?;
+        }
+        write!(f, "{}: {}", self.kind.name(), self.message)?;
+        for frame in &self.trace{
+            write!(f, "\n    in {}", frame.function)?;
+            if let Some(location) = &frame.location{
+                write!(f, " called at {}", location)?;
+            }
+        }
+        return Ok(());
+    }
+}
+
+
+impl ErrorValue{
+
+    /// Error text for the terminal, the kind is highlighted in bold
+    pub fn to_colored_string(&self) -> String{
+        let txt = self.to_string();
+        let name = self.kind.name() + ":";
+        return "\x1b[0;31m".to_string() + txt.replacen(name.as_str(), ("\x1b[1;31m".to_string() + name.as_str() + "\x1b[0;31m").as_str(), 1).as_str() + "\x1b[0m";
+    }
+}
+
+
+impl ErrorKind{
+
+    pub fn name(&self) -> String{
+        match self{
+            ErrorKind::RuntimeError => {
+                return "RuntimeError".to_string();
+            }
+            ErrorKind::TypeError => {
+                return "TypeError".to_string();
+            }
+            ErrorKind::ValueError => {
+                return "ValueError".to_string();
+            }
+            ErrorKind::ArgumentError => {
+                return "ArgumentError".to_string();
+            }
+            ErrorKind::IndexError => {
+                return "IndexError".to_string();
+            }
+            ErrorKind::KeyError => {
+                return "KeyError".to_string();
+            }
+            ErrorKind::NameError => {
+                return "NameError".to_string();
+            }
+            ErrorKind::ArithmeticError => {
+                return "ArithmeticError".to_string();
+            }
+            ErrorKind::IOError => {
+                return "IOError".to_string();
+            }
+            ErrorKind::ParseError => {
+                return "ParseError".to_string();
+            }
+            ErrorKind::UserError => {
+                return "UserError".to_string();
+            }
+            ErrorKind::CommandError => {
+                return "CommandError".to_string();
+            }
+        }
+    }
+
+    pub fn from_name(name: &str) -> ErrorKind{
+        match name{
+            "TypeError" => {
+                return ErrorKind::TypeError;
+            }
+            "ValueError" => {
+                return ErrorKind::ValueError;
+            }
+            "ArgumentError" => {
+                return ErrorKind::ArgumentError;
+            }
+            "IndexError" => {
+                return ErrorKind::IndexError;
+            }
+            "KeyError" => {
+                return ErrorKind::KeyError;
+            }
+            "NameError" => {
+                return ErrorKind::NameError;
+            }
+            "ArithmeticError" => {
+                return ErrorKind::ArithmeticError;
+            }
+            "IOError" => {
+                return ErrorKind::IOError;
+            }
+            "ParseError" => {
+                return ErrorKind::ParseError;
+            }
+            "UserError" => {
+                return ErrorKind::UserError;
+            }
+            "CommandError" => {
+                return ErrorKind::CommandError;
+            }
+            _ => {
+                return ErrorKind::RuntimeError;
+            }
+        }
+    }
+}
+
+
 impl Result{
 
+    pub fn error(kind: ErrorKind, message: String) -> Result{
+        return Result::Error(ErrorValue{
+            kind: kind,
+            message: message,
+            location: None,
+            payload: None,
+            trace: Vec::new()
+        });
+    }
+
+    /// Return, break and continue leave every expression they are evaluated in
+    pub fn is_control_flow(&self) -> bool{
+        return matches!(self, Result::Return(_) | Result::Break(_) | Result::Continue);
+    }
+
+    pub fn array(vec: Vec<Result>) -> Result{
+        return Result::Array(Arc::new(Mutex::new(vec)));
+    }
+
+    pub fn dict(map: HashMap<String, Result>) -> Result{
+        return Result::Dict(Arc::new(Mutex::new(map)));
+    }
+
+    /// Copies the array or dict itself, the values inside are still shared
+    pub fn copy(&self) -> Result{
+        match self{
+            Result::Array(vec) => {
+                return Result::array(vec.lock().unwrap().clone());
+            }
+            Result::Dict(map) => {
+                return Result::dict(map.lock().unwrap().clone());
+            }
+            _ => {
+                return self.clone();
+            }
+        }
+    }
+
+    /// Copies the array or dict and all arrays and dicts inside of it
+    pub fn deepcopy(&self) -> Result{
+        return self.deepcopy_with(&mut Vec::new());
+    }
+
+    /// Deep copy which reuses the copies of already copied containers, so cycles are copied as cycles
+    fn deepcopy_with(&self, copies: &mut Vec<(*const (), Result)>) -> Result{
+        match self{
+            Result::Array(vec) => {
+                let ptr = Arc::as_ptr(vec) as *const ();
+                if let Some((_, copy)) = copies.iter().find(|(other, _)| *other == ptr) {
+                    return copy.clone();
+                }
+                let copy = Arc::new(Mutex::new(Vec::new()));
+                copies.push((ptr, Result::Array(copy.clone())));
+                let vec = vec.lock().unwrap().clone();
+                let values: Vec<Result> = vec.iter().map(|value| value.deepcopy_with(copies)).collect();
+                *copy.lock().unwrap() = values;
+                return Result::Array(copy);
+            }
+            Result::Dict(map) => {
+                let ptr = Arc::as_ptr(map) as *const ();
+                if let Some((_, copy)) = copies.iter().find(|(other, _)| *other == ptr) {
+                    return copy.clone();
+                }
+                let copy = Arc::new(Mutex::new(HashMap::new()));
+                copies.push((ptr, Result::Dict(copy.clone())));
+                let map = map.lock().unwrap().clone();
+                let values: HashMap<String, Result> = map.iter().map(|(key, value)| (key.clone(), value.deepcopy_with(copies))).collect();
+                *copy.lock().unwrap() = values;
+                return Result::Dict(copy);
+            }
+            _ => {
+                return self.clone();
+            }
+        }
+    }
+
     pub fn typename(&self) -> String{
         match self{
             Result::Bool(_) => {
@@ -58,23 +483,41 @@ impl Result{
             Result::Dict(_) => {
                 return "dict".to_string();
             }
-            Result::Function{block:_,parameters:_,vars:_} => {
+            Result::Function{block:_,parameters:_,env:_} => {
                 return "function".to_string();
             }
-            Result::Range{start:_,end:_} => {
+            Result::Range(_) => {
                 return "range".to_string();
             }
+            Result::Task(_) => {
+                return "task".to_string();
+            }
+            Result::Channel(_) => {
+                return "channel".to_string();
+            }
             Result::None | _ => {
                 return "none".to_string();
             }
         }
     }
 
+    /// Prints the result, errors are colored if stdout is a terminal
     pub fn print(&self){
+        if let Result::Error(e) = self{
+            if std::io::stdout().is_terminal(){
+                println!("{}", e.to_colored_string());
+                return;
+            }
+        }
         println!("{}",self.to_string());
     }
 
     pub fn to_string(&self) -> String{
+        return self.to_string_with(&mut Vec::new());
+    }
+
+    /// Converts the result to a string, containers which are already being printed are shown as `[...]` or `{...}`
+    fn to_string_with(&self, visited: &mut Vec<*const ()>) -> String{
         match self {
             Result::Bool(txt) => {
                 return txt.to_string();
@@ -86,27 +529,41 @@ impl Result{
                 return txt.to_string();
             }
             Result::Array(vec) => {
+                let ptr = Arc::as_ptr(vec) as *const ();
+                if visited.contains(&ptr) {
+                    return "[...]".to_string();
+                }
+                let vec = vec.lock().unwrap().clone();
+                visited.push(ptr);
                 let mut txt = String::new();
                 for result in vec{
                     txt += ", ";
-                    txt += result.to_string().as_str();
+                    txt += result.to_string_with(visited).as_str();
                 }
+                visited.pop();
                 txt += " ]";
                 return "[".to_string() + &txt[1..];
             }
             Result::Dict(map) => {
+                let ptr = Arc::as_ptr(map) as *const ();
+                if visited.contains(&ptr) {
+                    return "{...}".to_string();
+                }
+                let map = map.lock().unwrap().clone();
+                visited.push(ptr);
                 let mut txt = String::new();
                 for (key,result) in map{
                     txt += ", ";
-                    txt = txt + key.to_string().as_str() + ": " + result.to_string().as_str()                    
+                    txt = txt + key.to_string().as_str() + ": " + result.to_string_with(visited).as_str()                    
                 }
+                visited.pop();
                 txt += " }";
                 return "{".to_string() + &txt[1..];
             }
-            Result::Function{block:_,parameters,vars:_} => {
+            Result::Function{block:_,parameters,env:_} => {
                 let mut txt = String::new();
-                for param in parameters{
-                    txt += ", ";
+                for param in parameters.iter(){
+                    txt += if param.variadic { ", ..." } else { ", " };
                     txt += &param.name;
                 }
                 if parameters.len() > 0{
@@ -114,16 +571,129 @@ impl Result{
                 }
                 return "( ".to_string() + &txt + " )->{}";
             }
-            Result::Range{start,end}=>{
-                return start.to_string() + ".." + &end.to_string();
+            Result::Range(range)=>{
+                let op = if range.inclusive { "..=" } else { ".." };
+                let mut txt = range.start.to_string() + op + &range.end.to_string();
+                if range.step != 1 {
+                    txt = txt + ".." + &range.step.to_string();
+                }
+                return txt;
             }
             Result::Float(txt) => {
                 return txt.to_string();
             }
+            Result::Task(task) => {
+                return "<task ".to_string() + &task.id.to_string() + ">";
+            }
+            Result::Channel(_) => {
+                return "<channel>".to_string();
+            }
             Result::Error(e) => {
-                return "\x1b[1;31mError:\x1b[0;31m ".to_string() + e.to_string().as_str() + "\x1b[0m";
+                return e.to_string();
             }
             _ => {return "".to_string();}
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::test::*;
+
+    #[test]
+    fn error_text_has_no_escape_codes() {
+        let mut e = ErrorValue {
+            kind: ErrorKind::KeyError,
+            message: "Key a does not exist".to_string(),
+            location: Some(Location { file: "f".to_string(), line: 2, column: 3 }),
+            payload: None,
+            trace: Vec::new()
+        };
+        e.trace.push(Frame { function: "g".to_string(), location: None });
+        assert_eq!(e.to_string(), "f:2:3: KeyError: Key a does not exist\n    in g");
+        assert!(e.to_colored_string().contains("\x1b[1;31mKeyError:"));
+    }
+
+    #[test]
+    fn error_kinds_by_name() {
+        for kind in vec![ErrorKind::TypeError, ErrorKind::IndexError, ErrorKind::IOError, ErrorKind::CommandError] {
+            assert_eq!(ErrorKind::from_name(&kind.name()), kind);
+        }
+    }
+
+    #[test]
+    fn channels_keep_values_after_closing() {
+        let channel = Channel::new();
+        channel.send(Result::Int(1));
+        channel.close();
+        assert!(matches!(channel.send(Result::Int(2)), Result::Error(_)));
+        assert!(matches!(channel.recv(), Some(Result::Int(1))));
+        assert!(channel.recv().is_none());
+    }
+
+    #[test]
+    fn recv_timeout_while_another_receiver_waits() {
+        let channel = Channel::new();
+        let _receiver = channel.receiver.lock().unwrap();
+        let start = Instant::now();
+        assert_eq!(channel.recv_timeout(Duration::from_millis(20)).unwrap_err(), RecvTimeoutError::Timeout);
+        assert!(start.elapsed() < Duration::from_secs(1));
+        assert_eq!(channel.try_recv().unwrap_err(), TryRecvError::Empty);
+    }
+
+    #[test]
+    fn recv_timeout_without_a_deadline() {
+        let channel = Channel::new();
+        channel.send(Result::Int(1));
+        channel.close();
+        assert!(matches!(channel.recv_timeout(Duration::MAX), Ok(Result::Int(1))));
+        assert_eq!(channel.recv_timeout(Duration::MAX).unwrap_err(), RecvTimeoutError::Disconnected);
+    }
+
+    #[test]
+    fn arrays_and_dicts_are_shared() {
+        assert_eq!(text("a = [1]; b = a; push(b, 2); a"), "[ 1, 2 ]");
+        assert_eq!(text("a = [1]; f = (x) -> { push(x, 2) }; f(a); a"), "[ 1, 2 ]");
+        assert_eq!(text("d = {\"k\": 1}; f = (x) -> { x[\"k\"] = 2 }; f(d); d"), "{ k: 2 }");
+    }
+
+    #[test]
+    fn copies() {
+        assert_eq!(text("a = [[1]]; b = copy(a); push(b, 2); push(b[0], 3); [a, b]"), "[ [ [ 1, 3 ] ], [ [ 1, 3 ], 2 ] ]");
+        assert_eq!(text("a = [[1]]; b = deepcopy(a); push(b[0], 3); a"), "[ [ 1 ] ]");
+        assert_eq!(text("copy(5)"), "5");
+    }
+
+    #[test]
+    fn cycles() {
+        assert_eq!(text("a = []; push(a, a); a"), "[ [...] ]");
+        assert_eq!(text("d = {}; d[\"self\"] = d; d"), "{ self: {...} }");
+        assert_eq!(text("a = []; push(a, a); b = deepcopy(a); push(b, 1); [len(a), len(b[0])]"), "[ 1, 2 ]");
+        assert_eq!(text("a = []; push(a, a); b = []; push(b, b); a == b"), "true");
+    }
+
+    #[test]
+    fn ranges_near_the_bounds_of_ints() {
+        let range = Range { start: i64::MAX - 7, end: i64::MAX, step: 1, inclusive: true };
+        assert_eq!(range.len(), 8);
+        assert_eq!(range.iter().last(), Some(i64::MAX));
+        assert_eq!(range.get(7), Some(i64::MAX));
+        assert_eq!(range.get(8), None);
+        assert!(range.contains(i64::MAX));
+        let range = Range { start: i64::MIN + 1, end: i64::MIN, step: -1, inclusive: true };
+        assert_eq!(range.iter().collect::<Vec<i64>>(), vec![i64::MIN + 1, i64::MIN]);
+        let range = Range { start: i64::MIN, end: i64::MAX, step: 1, inclusive: true };
+        assert_eq!(range.len(), usize::MAX);
+    }
+
+    #[test]
+    fn ranges_with_steps() {
+        let range = Range { start: 10, end: 0, step: -3, inclusive: false };
+        assert_eq!(range.iter().collect::<Vec<i64>>(), vec![10, 7, 4, 1]);
+        assert_eq!(range.len(), 4);
+        assert_eq!(range.get(-1), Some(1));
+        assert!(range.contains(4));
+        assert!(!range.contains(5));
+    }
+}
@@ -8,19 +8,30 @@ use dirs;
 use linefeed::terminal::DefaultTerminal;
 use linefeed::{Interface, Signal};
 use std::io::prelude::*;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
 #[derive(Clone)]
-pub struct Runtime<'a> {
-    pub interface: Arc<Interface<DefaultTerminal>>,
-    pub basectx: Arc<Context<'a>>,
+pub struct Runtime {
+    pub interface: Option<Arc<Interface<DefaultTerminal>>>,
+    pub basectx: Arc<Context>,
+    /// Files which are currently executed, the last one is the innermost include
+    pub files: Arc<Mutex<Vec<String>>>,
+    /// Tasks started with async, finished tasks are removed when listing them
+    pub tasks: Arc<Mutex<Vec<result::Task>>>,
+    next_task: Arc<Mutex<usize>>,
+    /// Set once strict mode stopped a block at an error, even if the error was used as a value afterwards
+    aborted: Arc<Mutex<bool>>,
 }
 
-impl<'a> Runtime<'a> {
-    pub fn new() -> Arc<Runtime<'a>> {
+impl Runtime {
+    pub fn new() -> Arc<Runtime> {
         let runtime = Runtime {
             basectx: Arc::new(Context::new()),
-            interface: Runtime::make_interface(),
+            interface: Some(Runtime::make_interface()),
+            files: Arc::new(Mutex::new(Vec::new())),
+            tasks: Arc::new(Mutex::new(Vec::new())),
+            next_task: Arc::new(Mutex::new(1)),
+            aborted: Arc::new(Mutex::new(false)),
         };
         let runtime = Arc::new(runtime);
         runtime.clone().init();
@@ -29,6 +40,28 @@ impl<'a> Runtime<'a> {
         return runtime;
     }
 
+    /// Creates a runtime without a terminal interface and without history, used for non-interactive runs
+    pub fn new_headless() -> Arc<Runtime> {
+        let runtime = Runtime::new_bare();
+        runtime.clone().load_config();
+        return runtime;
+    }
+
+    /// Creates a headless runtime which does not run ~/.cashrc either, so tests don't depend on the user's config
+    pub fn new_bare() -> Arc<Runtime> {
+        let runtime = Runtime {
+            basectx: Arc::new(Context::new()),
+            interface: None,
+            files: Arc::new(Mutex::new(Vec::new())),
+            tasks: Arc::new(Mutex::new(Vec::new())),
+            next_task: Arc::new(Mutex::new(1)),
+            aborted: Arc::new(Mutex::new(false)),
+        };
+        let runtime = Arc::new(runtime);
+        runtime.clone().init();
+        return runtime;
+    }
+
     fn make_interface() -> Arc<Interface<DefaultTerminal>> {
         let interface = Interface::new("cash").unwrap();
         interface.set_report_signal(Signal::Break, true);
@@ -45,6 +78,7 @@ impl<'a> Runtime<'a> {
         );
         self.basectx
             .set_var("SUFFIX", result::Result::String("\n".to_string()));
+        self.basectx.set_var("STRICT", result::Result::Bool(false));
 
         self.basectx
             .set_var("APPNAME", result::Result::String(APPNAME.to_string()));
@@ -64,16 +98,16 @@ impl<'a> Runtime<'a> {
     }
 
     fn load_history(self: Arc<Self>) {
-        if let Some(path) = dirs::home_dir() {
+        if let (Some(path), Some(interface)) = (dirs::home_dir(), &self.interface) {
             let path = path.join(std::path::Path::new(".cash_history"));
-            self.interface.load_history(path);
+            interface.load_history(path);
         }
     }
 
     fn save_history(self: Arc<Self>) {
-        if let Some(path) = dirs::home_dir() {
+        if let (Some(path), Some(interface)) = (dirs::home_dir(), &self.interface) {
             let path = path.join(std::path::Path::new(".cash_history"));
-            self.interface.save_history(path);
+            interface.save_history(path);
         }
     }
 
@@ -87,16 +121,82 @@ impl<'a> Runtime<'a> {
     }
 
     pub fn exec(self: Arc<Self>, text: String) -> Result<result::Result> {
-        let res = interpreter::interpret(text.clone(), self.clone(), self.basectx.clone())?;
-        res.print();
+        let res = self.clone().run(text)?;
+        self.print_result(&res);
         Ok(res)
     }
 
+    /// Prints the value of a command, objects with __str__ are printed like println prints them
+    pub fn print_result(self: Arc<Self>, res: &result::Result) {
+        if let result::Result::Error(_) = res {
+            res.print();
+        } else {
+            interpreter::display_value(res.clone(), self).print();
+        }
+    }
+
+    /// Interprets the text without printing the result, a panic while interpreting becomes an error instead of ending the session
+    pub fn run(self: Arc<Self>, text: String) -> Result<result::Result> {
+        return Runtime::catch_panic(move || interpreter::interpret(text, self.clone(), self.basectx.clone()));
+    }
+
+    fn catch_panic(run: impl FnOnce() -> Result<result::Result>) -> Result<result::Result> {
+        match std::panic::catch_unwind(std::panic::AssertUnwindSafe(run)) {
+            std::result::Result::Ok(res) => {
+                return res;
+            }
+            Err(panic) => {
+                let mut message = "Internal error".to_string();
+                if let Some(text) = panic.downcast_ref::<&str>() {
+                    message = message + ": " + text;
+                } else if let Some(text) = panic.downcast_ref::<String>() {
+                    message = message + ": " + text;
+                }
+                return Ok(result::Result::error(result::ErrorKind::RuntimeError, message));
+            }
+        }
+    }
+
     pub fn exec_file(self: Arc<Self>, path: &std::path::Path) -> Result<result::Result> {
+        let res = self.clone().run_file(path)?;
+        self.print_result(&res);
+        Ok(res)
+    }
+
+    /// Interprets the file without printing the result
+    pub fn run_file(self: Arc<Self>, path: &std::path::Path) -> Result<result::Result> {
         let mut file = std::fs::File::open(path)?;
         let mut contents = String::new();
         file.read_to_string(&mut contents)?;
-        return self.exec(contents);
+        self.files.lock().unwrap().push(path.to_string_lossy().to_string());
+        let res = self.clone().run(contents);
+        self.files.lock().unwrap().pop();
+        return res;
+    }
+
+    /// Name of the file which is currently executed, used for error locations
+    pub fn current_file(&self) -> String {
+        if let Some(file) = self.files.lock().unwrap().last() {
+            return file.clone();
+        }
+        return "<input>".to_string();
+    }
+
+    /// Strict mode stops scripts at the first error
+    pub fn is_strict(&self) -> bool {
+        if let result::Result::Bool(true) = self.basectx.var("STRICT") {
+            return true;
+        }
+        return false;
+    }
+
+    pub fn set_aborted(&self) {
+        *self.aborted.lock().unwrap() = true;
+    }
+
+    /// Whether strict mode stopped a block at an error, errors caught by try don't count
+    pub fn aborted(&self) -> bool {
+        return *self.aborted.lock().unwrap();
     }
 
     fn var_string(self: &Arc<Self>, name: &str) -> String {
@@ -106,7 +206,7 @@ impl<'a> Runtime<'a> {
         } else if let result::Result::Function {
             block: _,
             parameters: _,
-            vars: _,
+            env: _,
         } = var
         {
             if let result::Result::String(text) =
@@ -114,19 +214,19 @@ impl<'a> Runtime<'a> {
             {
                 return text;
             } else {
-                println!(
-                    "{}",
-                    result::Result::Error("Function needs to return a string".to_string())
-                        .to_string()
-                );
+                result::Result::error(
+                    result::ErrorKind::TypeError,
+                    "Function needs to return a string".to_string(),
+                )
+                .print();
                 return " ".to_string();
             }
         } else {
-            println!(
-                "{}",
-                result::Result::Error(name.to_string() + " needs to be a string or a function")
-                    .to_string()
-            );
+            result::Result::error(
+                result::ErrorKind::TypeError,
+                name.to_string() + " needs to be a string or a function",
+            )
+            .print();
             return " ".to_string();
         }
     }
@@ -137,20 +237,43 @@ impl<'a> Runtime<'a> {
         return Ok(());
     }
 
-    pub fn print_end(self: &Arc<Runtime<'a>>) -> Result<()> {
+    pub fn print_end(self: &Arc<Runtime>) -> Result<()> {
         let text = self.var_string("SUFFIX");
         print!("{}", text);
         return Ok(());
     }
 
     pub fn include_file(self: Arc<Self>, path: &std::path::Path) -> Result<result::Result>{
+        let path = std::fs::canonicalize(path)?;
         let cd = std::env::current_dir().unwrap_or_default();
-        std::env::set_current_dir(std::fs::canonicalize(path.parent().unwrap()).unwrap());
-        let res = self.exec_file(path);
+        if let Some(parent) = path.parent() {
+            std::env::set_current_dir(parent)?;
+        }
+        let res = self.exec_file(&path);
         std::env::set_current_dir(cd);
         return res;
     }
 
+    /// Registers the thread of an async statement and returns its task
+    pub fn add_task(&self, handle: std::thread::JoinHandle<result::Result>) -> result::Task {
+        let mut next = self.next_task.lock().unwrap();
+        let task = result::Task {
+            id: *next,
+            handle: Arc::new(Mutex::new(Some(handle))),
+            result: Arc::new(Mutex::new(None)),
+        };
+        *next += 1;
+        self.tasks.lock().unwrap().push(task.clone());
+        return task;
+    }
+
+    /// Tasks which are still running
+    pub fn running_tasks(&self) -> Vec<result::Task> {
+        let mut tasks = self.tasks.lock().unwrap();
+        tasks.retain(|task| task.is_running());
+        return tasks.clone();
+    }
+
     pub fn clear(&self) {
         println!("\x1b[2J\x1b[1;1H");
     }
@@ -165,7 +288,8 @@ impl<'a> Runtime<'a> {
     }
 
     pub fn which(name: &str) -> Result<std::path::PathBuf> {
-        for mut path in std::env::split_paths(&std::env::var_os("PATH").unwrap()) {
+        let paths = std::env::var_os("PATH").ok_or(anyhow!("PATH is not set"))?;
+        for mut path in std::env::split_paths(&paths) {
             path.push(name);
             if path.exists() {
                 return Ok(path);
@@ -174,3 +298,88 @@ impl<'a> Runtime<'a> {
         return Err(anyhow!("Could not find file in path"));
     }
 }
+
+/// Helpers for the tests of the interpreter, scripts run in a runtime without terminal like with -c
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    /// Value of the last statement of the script
+    pub fn run(script: &str) -> result::Result {
+        return Runtime::new_bare().run(script.to_string()).expect("the script should parse");
+    }
+
+    /// Text of the value of the script, like it is printed
+    pub fn text(script: &str) -> String {
+        return run(script).to_string();
+    }
+
+    /// Kind of the error the script evaluated to, None if it did not evaluate to an error
+    pub fn error_kind(script: &str) -> Option<result::ErrorKind> {
+        if let result::Result::Error(e) = run(script) {
+            return Some(e.kind);
+        }
+        return None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::test::*;
+    use super::*;
+
+    #[test]
+    fn headless_runtime_runs_commands() {
+        let runtime = Runtime::new_bare();
+        assert!(runtime.interface.is_none());
+        assert_eq!(runtime.run("x = 2; x * 3".to_string()).unwrap().to_string(), "6");
+    }
+
+    #[test]
+    fn command_value_is_the_last_statement() {
+        assert_eq!(text("1 + 2"), "3");
+        assert_eq!(text("a = [1]; push(a, 2); a"), "[ 1, 2 ]");
+        assert!(matches!(run("x = 1"), result::Result::None));
+    }
+
+    #[test]
+    fn parse_errors_are_not_values() {
+        assert!(Runtime::new_bare().run("1 +".to_string()).is_err());
+    }
+
+    #[test]
+    fn strict_files_stop_at_the_first_error() {
+        let path = std::env::temp_dir().join("cash-strict-test.cash");
+        std::fs::write(&path, "c = 1\nx = 5 % 0\nc = 2\n").unwrap();
+        let runtime = Runtime::new_bare();
+        runtime.basectx.set_var("STRICT", result::Result::Bool(true));
+        let res = runtime.clone().run_file(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        if let result::Result::Error(e) = res {
+            assert_eq!(e.location.unwrap().line, 2);
+        } else {
+            panic!("the file should stop at the error");
+        }
+        assert_eq!(runtime.basectx.var("c").to_string(), "1");
+        assert!(runtime.aborted());
+    }
+
+    #[test]
+    fn results_are_displayed_with_str() {
+        let runtime = Runtime::new_bare();
+        let res = runtime.clone().run("P = class({\"__str__\": () -> { \"P\" }}); [P.new()]".to_string()).unwrap();
+        assert_eq!(interpreter::display_value(res, runtime).to_string(), "[ P ]");
+    }
+
+    #[test]
+    fn panics_become_errors() {
+        let res = Runtime::catch_panic(|| panic!("boom")).unwrap();
+        if let result::Result::Error(e) = res {
+            assert_eq!(e.kind, result::ErrorKind::RuntimeError);
+            assert_eq!(e.message, "Internal error: boom");
+        } else {
+            panic!("the panic should be an error");
+        }
+        assert_eq!(text("1 + 1"), "2");
+    }
+}